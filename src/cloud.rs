@@ -0,0 +1,923 @@
+use std::{collections::HashMap, ops::RangeInclusive, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use crate::{color::Hsbk, error::LifxError};
+use crate::types::{Capabilities, Error, ErrorResponse, RateLimit, ListLightResponse, ToggledLightsResponse, SetStateResponse, SetStatesResponse, SceneResponse, ValidateColorResponse};
+
+use log::debug;
+use reqwest::{Method, Proxy, RequestBuilder, Response, StatusCode, header::HeaderMap};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value, Number};
+use urlencoding::encode;
+
+pub const LIFX_URL_TEMPLATE: &str = "https://api.lifx.com/v1";
+
+const USER_AGENT: &str = concat!("lifx-cli/", env!("CARGO_PKG_VERSION"));
+
+/* Including the first attempt */
+const MAX_ATTEMPTS: u32 = 4;
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+/* The longest a request waits in total between retries. A rate limit resetting later than this fails straight away */
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(15);
+
+/* The longest transition the API accepts, in seconds */
+const MAX_DURATION: f64 = 3155760000.0;
+
+/*
+    Settings for the HTTP client shared by every request
+*/
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /* Replaces the public API, e.g. to point at a stub server */
+    pub base_url: String,
+    /* Sends every request through this proxy instead of the one from HTTP_PROXY / HTTPS_PROXY */
+    pub proxy: Option<String>,
+    pub connect_timeout: Duration,
+    /* For the whole request, including reading the response */
+    pub timeout: Duration,
+    /* Print every response status and the remaining rate limit quota to stderr */
+    pub verbose: bool,
+}
+
+impl Default for ClientOptions {
+    fn default() -> ClientOptions {
+        ClientOptions {
+            base_url: String::from(LIFX_URL_TEMPLATE),
+            proxy: None,
+            connect_timeout: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+            verbose: false,
+        }
+    }
+}
+
+/*
+    Talks to the LIFX HTTP API, validating requests locally before they're sent
+    https://api.developer.lifx.com/docs
+*/
+#[derive(Clone)]
+pub struct Client {
+    token: String,
+    base_url: String,
+    verbose: bool,
+    /* Reused by every request so multi-step commands share connections */
+    client: reqwest::Client,
+}
+
+impl Client {
+    pub fn new(key: &str, options: &ClientOptions) -> Result<Client, LifxError> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .connect_timeout(options.connect_timeout)
+            .timeout(options.timeout);
+
+        if let Some(proxy) = &options.proxy {
+            let proxy = Proxy::all(proxy).map_err(|error| LifxError::Config(format!("invalid proxy '{}': {}", proxy, error)))?;
+            builder = builder.proxy(proxy);
+        }
+
+        let client = builder.build().map_err(|error| LifxError::Config(error.to_string()))?;
+
+        Ok(Client {
+            token: String::from(key),
+            base_url: options.base_url.trim_end_matches('/').to_owned(),
+            verbose: options.verbose,
+            client,
+        })
+    }
+
+    /*
+        https://api.developer.lifx.com/docs/list-lights
+    */
+    pub async fn list_lights(&self, selector: &str) -> Result<Vec<ListLightResponse>, LifxError> {
+        debug!("Sending request");
+
+        let request = self.client
+            .get(format!("{}{}", self.base_url, "/lights/".to_owned() + encode(selector).into_owned().as_str()));
+
+        let res = self.send(request).await?;
+
+        debug!("Parsing json");
+
+        let lights = res.json::<Vec<ListLightResponse>>().await?;
+
+        debug!("{}", serde_json::to_string_pretty(&lights)?);
+
+        Ok(lights)
+    }
+
+    /*
+        Check every light matched by the selector has a capability, listing the ones that don't
+    */
+    async fn require_capability<F>(&self, selector: &str, capability: &str, has_capability: F) -> Result<(), LifxError>
+    where
+        F: Fn(&Capabilities) -> bool,
+    {
+        let lights = self.list_lights(selector).await?;
+
+        if lights.is_empty() {
+            return Err(LifxError::NotFound(format!("No lights match the selector '{}'", selector)));
+        }
+
+        let unsupported: Vec<&ListLightResponse> = lights
+            .iter()
+            .filter(|light| !has_capability(&light.product.capabilities))
+            .collect();
+
+        if !unsupported.is_empty() {
+            let mut message = format!("The selector '{}' matches lights without {} support:", selector, capability);
+            for light in unsupported {
+                message.push_str(&format!("\n  {} ({}, {})", light.label, light.id, light.product.name));
+            }
+            return Err(LifxError::Validation(message));
+        }
+
+        Ok(())
+    }
+
+    pub async fn toggle_lights(&self, selector: &str, duration: Option<f64>) -> Result<ToggledLightsResponse, LifxError> {
+        debug!("Sending request");
+
+        let mut body = HashMap::new();
+
+        if let Some(duration) = duration {
+            debug!("duration: {}", duration);
+            body.insert("duration".to_string(), duration.to_string());
+        }
+
+        debug!("{:?}", body);
+
+        let request = self.client
+            .post(format!("{}{}", self.base_url, "/lights/".to_owned() + encode(selector).into_owned().as_str() + "/toggle"))
+            .json(&body);
+
+        let res = self.send(request).await?;
+
+        debug!("Light/s toggled");
+
+        let toggle_results = res.json::<ToggledLightsResponse>().await?;
+
+        Ok(toggle_results)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn set_state(
+        &self,
+        selector: &str,
+        power: Option<&String>,
+        color: Option<&String>,
+        brightness: Option<f64>,
+        duration: Option<f64>,
+        infrared: Option<f64>,
+        fast: Option<bool>,
+    ) -> Result<SetStateResponse, LifxError> {
+        let mut body = Map::new();
+
+        if let Some(color) = color {
+            body.insert("color".to_string(), Value::String(color.to_string()));
+        }
+
+        if let Some(power) = power {
+            body.insert("power".to_string(), Value::String(power.to_string()));
+        }
+
+        for (field, value, max) in [("brightness", brightness, 1.0), ("duration", duration, MAX_DURATION), ("infrared", infrared, 1.0)] {
+            if let Some(value) = value {
+                body.insert(field.to_string(), number(field, check_range(field, value, 0.0..=max)?)?);
+            }
+        }
+
+        if let Some(true) = fast {
+            body.insert("fast".to_string(), Value::Bool(true));
+        }
+
+        self.validate_state(&body)?;
+
+        let request = self.client
+            .put(format!("{}{}", self.base_url, "/lights/".to_owned() + encode(selector).into_owned().as_str() + "/state"))
+            .json(&body);
+
+        let res = self.send(request).await?;
+
+        results(res).await
+    }
+
+    /*
+        https://api.developer.lifx.com/docs/state-delta
+    */
+    #[allow(clippy::too_many_arguments)]
+    pub async fn adjust(
+        &self,
+        selector: &str,
+        power: Option<&String>,
+        duration: Option<f64>,
+        infrared: Option<f64>,
+        hue: Option<f64>,
+        saturation: Option<f64>,
+        brightness: Option<f64>,
+        kelvin: Option<i64>,
+    ) -> Result<SetStateResponse, LifxError> {
+        let mut body = Map::new();
+
+        if let Some(power) = power {
+            body.insert("power".to_string(), Value::String(power.to_string()));
+        }
+
+        if let Some(duration) = duration {
+            body.insert("duration".to_string(), number("duration", check_range("duration", duration, 0.0..=MAX_DURATION)?)?);
+        }
+
+        self.validate_state(&body)?;
+
+        for (field, delta, max) in [("infrared", infrared, 1.0), ("hue", hue, 360.0), ("saturation", saturation, 1.0), ("brightness", brightness, 1.0)] {
+            if let Some(delta) = delta {
+                body.insert(field.to_string(), number(field, check_range(field, delta, -max..=max)?)?);
+            }
+        }
+
+        if let Some(kelvin) = kelvin {
+            body.insert("kelvin".to_string(), Value::Number(Number::from(kelvin)));
+        }
+
+        debug!("{:?}", body);
+
+        let request = self.client
+            .post(format!("{}{}", self.base_url, "/lights/".to_owned() + encode(selector).into_owned().as_str() + "/state/delta"))
+            .json(&body);
+
+        let res = self.send(request).await?;
+
+        let set_state_response = res.json::<SetStateResponse>().await?;
+
+        Ok(set_state_response)
+    }
+
+    /*
+        https://api.developer.lifx.com/docs/breathe-effect
+    */
+    #[allow(clippy::too_many_arguments)]
+    pub async fn breathe(
+        &self,
+        selector: &str,
+        color: &str,
+        from_color: Option<&String>,
+        period: Option<f64>,
+        cycles: Option<f64>,
+        persist: bool,
+        power_on: Option<bool>,
+        peak: Option<f64>,
+    ) -> Result<SetStateResponse, LifxError> {
+        let mut body = Map::new();
+
+        if let Some(peak) = peak {
+            body.insert("peak".to_string(), number("peak", check_range("peak", peak, 0.0..=1.0)?)?);
+        }
+
+        self.waveform_effect(selector, "breathe", body, color, from_color, period, cycles, persist, power_on).await
+    }
+
+    /*
+        https://api.developer.lifx.com/docs/pulse-effect
+    */
+    #[allow(clippy::too_many_arguments)]
+    pub async fn pulse(
+        &self,
+        selector: &str,
+        color: &str,
+        from_color: Option<&String>,
+        period: Option<f64>,
+        cycles: Option<f64>,
+        persist: bool,
+        power_on: Option<bool>,
+    ) -> Result<SetStateResponse, LifxError> {
+        self.waveform_effect(selector, "pulse", Map::new(), color, from_color, period, cycles, persist, power_on).await
+    }
+
+    /*
+        Validate and send the parameters shared by the breathe and pulse effects
+    */
+    #[allow(clippy::too_many_arguments)]
+    async fn waveform_effect(
+        &self,
+        selector: &str,
+        effect: &str,
+        mut body: Map<String, Value>,
+        color: &str,
+        from_color: Option<&String>,
+        period: Option<f64>,
+        cycles: Option<f64>,
+        persist: bool,
+        power_on: Option<bool>,
+    ) -> Result<SetStateResponse, LifxError> {
+        self.validate_color(color)?;
+
+        body.insert("color".to_string(), Value::String(color.to_string()));
+
+        if let Some(from_color) = from_color {
+            self.validate_color(from_color)?;
+
+            body.insert("from_color".to_string(), Value::String(from_color.to_string()));
+        }
+
+        for (field, value) in [("period", period), ("cycles", cycles)] {
+            if let Some(value) = value {
+                body.insert(field.to_string(), number(field, positive(field, value)?)?);
+            }
+        }
+
+        body.insert("persist".to_string(), Value::Bool(persist));
+
+        if let Some(power_on) = power_on {
+            body.insert("power_on".to_string(), Value::Bool(power_on));
+        }
+
+        self.post_effect(selector, effect, &body).await
+    }
+
+    /*
+        https://api.developer.lifx.com/docs/move-effect
+        https://api.developer.lifx.com/docs/morph-effect
+        https://api.developer.lifx.com/docs/flame-effect
+        https://api.developer.lifx.com/docs/clouds-effect
+        https://api.developer.lifx.com/docs/sunrise-effect
+        https://api.developer.lifx.com/docs/sunset-effect
+    */
+    #[allow(clippy::too_many_arguments)]
+    pub async fn effect(
+        &self,
+        selector: &str,
+        effect: &str,
+        direction: Option<&String>,
+        period: Option<f64>,
+        cycles: Option<f64>,
+        duration: Option<f64>,
+        palette: Vec<&String>,
+        power_on: Option<bool>,
+        fast: bool,
+    ) -> Result<SetStateResponse, LifxError> {
+        let mut body = Map::new();
+
+        for (field, value) in [("period", period), ("cycles", cycles), ("duration", duration)] {
+            if let Some(value) = value {
+                body.insert(field.to_string(), number(field, positive(field, value)?)?);
+            }
+        }
+
+        /* The move effect runs on strips, the others on tiles, candles and other matrix lights */
+        match effect {
+            "move" => self.require_capability(selector, "multizone", |capabilities| capabilities.has_multizone).await?,
+            _ => self.require_capability(selector, "matrix", |capabilities| capabilities.has_matrix).await?,
+        }
+
+        if let Some(direction) = direction {
+            body.insert("direction".to_string(), Value::String(direction.to_string()));
+        }
+
+        if !palette.is_empty() {
+            for color in &palette {
+                self.validate_color(color)?;
+            }
+
+            body.insert("palette".to_string(), Value::Array(palette.iter().map(|color| Value::String(color.to_string())).collect()));
+        }
+
+        if let Some(power_on) = power_on {
+            body.insert("power_on".to_string(), Value::Bool(power_on));
+        }
+
+        if fast {
+            body.insert("fast".to_string(), Value::Bool(fast));
+        }
+
+        self.post_effect(selector, effect, &body).await
+    }
+
+    /*
+        https://api.developer.lifx.com/docs/effects-off
+    */
+    pub async fn effects_off(&self, selector: &str, power_off: bool) -> Result<SetStateResponse, LifxError> {
+        let mut body = Map::new();
+
+        body.insert("power_off".to_string(), Value::Bool(power_off));
+
+        self.post_effect(selector, "off", &body).await
+    }
+
+    async fn post_effect(&self, selector: &str, effect: &str, body: &Map<String, Value>) -> Result<SetStateResponse, LifxError> {
+        debug!("{:?}", body);
+
+        let request = self.client
+            .post(format!("{}{}", self.base_url, "/lights/".to_owned() + encode(selector).into_owned().as_str() + "/effects/" + effect))
+            .json(body);
+
+        let res = self.send(request).await?;
+
+        results(res).await
+    }
+
+    /*
+        https://api.developer.lifx.com/docs/set-states
+    */
+    pub async fn set_states(&self, states: Map<String, Value>, fast: bool) -> Result<SetStatesResponse, LifxError> {
+        let mut body = states;
+
+        self.validate_states(&body, true)?;
+
+        if fast {
+            body.insert("fast".to_string(), Value::Bool(fast));
+        }
+
+        debug!("{:?}", body);
+
+        let request = self.client
+            .put(format!("{}{}", self.base_url, "/lights/states"))
+            .json(&body);
+
+        let res = self.send(request).await?;
+
+        results(res).await
+    }
+
+    /*
+        https://api.developer.lifx.com/docs/clean
+    */
+    pub async fn clean(&self, selector: &str, duration: Option<u64>, stop: bool) -> Result<SetStateResponse, LifxError> {
+        let mut body = Map::new();
+
+        body.insert("stop".to_string(), Value::Bool(stop));
+
+        if let Some(duration) = duration {
+            if duration > 86400 {
+                return Err(LifxError::Validation(String::from("'duration' must be between 0 and 86400 seconds")));
+            }
+
+            body.insert("duration".to_string(), Value::Number(Number::from(duration)));
+        }
+
+        self.require_capability(selector, "HEV (Clean)", |capabilities| capabilities.has_hev).await?;
+
+        debug!("{:?}", body);
+
+        let request = self.client
+            .post(format!("{}{}", self.base_url, "/lights/".to_owned() + encode(selector).into_owned().as_str() + "/clean"))
+            .json(&body);
+
+        let res = self.send(request).await?;
+
+        let clean_response = res.json::<SetStateResponse>().await?;
+
+        Ok(clean_response)
+    }
+
+    /*
+        https://api.developer.lifx.com/docs/list-scenes
+    */
+    pub async fn list_scenes(&self) -> Result<Vec<SceneResponse>, LifxError> {
+        let request = self.client
+            .get(format!("{}{}", self.base_url, "/scenes"));
+
+        let res = self.send(request).await?;
+
+        let scenes = res.json::<Vec<SceneResponse>>().await?;
+
+        debug!("{}", serde_json::to_string_pretty(&scenes)?);
+
+        Ok(scenes)
+    }
+
+    /*
+        https://api.developer.lifx.com/docs/activate-scene
+    */
+    pub async fn activate_scene(
+        &self,
+        scene: &str,
+        duration: Option<f64>,
+        ignore: Vec<&String>,
+        overrides: Vec<&String>,
+        fast: bool,
+    ) -> Result<SetStateResponse, LifxError> {
+        let mut body = Map::new();
+
+        if let Some(duration) = duration {
+            body.insert("duration".to_string(), number("duration", check_range("duration", duration, 0.0..=MAX_DURATION)?)?);
+        }
+
+        let mut ignored: Vec<Value> = vec![];
+
+        for field in &ignore {
+            /* The API has no color field to ignore, it's the hue, saturation and kelvin together */
+            let fields = match field.as_str() {
+                "color" => vec!["hue", "saturation", "kelvin"],
+                "power" | "infrared" | "duration" | "intensity" | "hue" | "saturation" | "brightness" | "kelvin" => vec![field.as_str()],
+                _ => return Err(LifxError::Validation(format!("'{}' can't be ignored. Expected power, infrared, duration, intensity, color, hue, saturation, brightness or kelvin", field))),
+            };
+
+            for field in fields {
+                if !ignored.iter().any(|ignored| ignored == field) {
+                    ignored.push(Value::String(field.to_string()));
+                }
+            }
+        }
+
+        if !ignored.is_empty() {
+            body.insert("ignore".to_string(), Value::Array(ignored));
+        }
+
+        if !overrides.is_empty() {
+            let mut state = Map::new();
+
+            for pair in overrides {
+                let (field, value) = match pair.split_once('=') {
+                    Some(pair) => pair,
+                    None => {
+                        return Err(LifxError::Validation(format!("Overrides should be in the form field=value, got '{}'", pair)));
+                    }
+                };
+
+                let value = match value.parse::<f64>().ok().and_then(Number::from_f64) {
+                    Some(number) => Value::Number(number),
+                    None => Value::String(value.to_string()),
+                };
+
+                state.insert(field.to_string(), value);
+            }
+
+            self.validate_state(&state)?;
+
+            body.insert("overrides".to_string(), Value::Object(state));
+        }
+
+        let scenes = self.list_scenes().await?;
+
+        /* Accept either the UUID or the (case insensitive) name of the scene */
+        let matching: Vec<&SceneResponse> = match scenes.iter().find(|candidate| candidate.uuid == scene) {
+            Some(found) => vec![found],
+            None => scenes.iter().filter(|candidate| candidate.name.eq_ignore_ascii_case(scene)).collect(),
+        };
+
+        let uuid = match matching[..] {
+            [found] => found.uuid.clone(),
+            [] => return Err(LifxError::NotFound(format!("No scene with the name or UUID '{}'", scene))),
+            _ => {
+                let mut message = format!("More than one scene is named '{}', use the UUID instead:", scene);
+                for found in matching {
+                    message.push_str(&format!("\n  {}", found.uuid));
+                }
+                return Err(LifxError::Validation(message));
+            }
+        };
+
+        if fast {
+            body.insert("fast".to_string(), Value::Bool(fast));
+        }
+
+        debug!("{:?}", body);
+
+        let request = self.client
+            .put(format!("{}{}", self.base_url, "/scenes/scene_id:".to_owned() + encode(&uuid).into_owned().as_str() + "/activate"))
+            .json(&body);
+
+        let res = self.send(request).await?;
+
+        results(res).await
+    }
+
+    /*
+        https://api.developer.lifx.com/docs/cycle
+    */
+    pub async fn cycle(&self, selector: &str, states: Map<String, Value>, direction: Option<&String>) -> Result<SetStateResponse, LifxError> {
+        let mut body = states;
+
+        self.validate_states(&body, false)?;
+
+        if let Some(direction) = direction {
+            body.insert("direction".to_string(), Value::String(direction.to_string()));
+        }
+
+        debug!("{:?}", body);
+
+        let request = self.client
+            .post(format!("{}{}", self.base_url, "/lights/".to_owned() + encode(selector).into_owned().as_str() + "/cycle"))
+            .json(&body);
+
+        let res = self.send(request).await?;
+
+        let cycle_response = res.json::<SetStateResponse>().await?;
+
+        Ok(cycle_response)
+    }
+
+    /*
+        Validate the 'states' list and optional 'defaults' of a set-states or cycle body
+    */
+    fn validate_states(&self, body: &Map<String, Value>, require_selector: bool) -> Result<(), LifxError> {
+        let states = match body.get("states").and_then(Value::as_array) {
+            Some(states) if !states.is_empty() => states,
+            _ => {
+                return Err(LifxError::Validation(String::from("'states' must be a non-empty list of states")));
+            }
+        };
+
+        for state in states {
+            let state = match state.as_object() {
+                Some(state) if !require_selector || state.get("selector").is_some_and(Value::is_string) => state,
+                _ if require_selector => {
+                    return Err(LifxError::Validation(String::from("Every entry in 'states' must be an object with a 'selector'")));
+                },
+                _ => {
+                    return Err(LifxError::Validation(String::from("Every entry in 'states' must be an object")));
+                }
+            };
+
+            self.validate_state(state)?;
+        }
+
+        if let Some(defaults) = body.get("defaults") {
+            match defaults.as_object() {
+                Some(defaults) => self.validate_state(defaults)?,
+                None => return Err(LifxError::Validation(String::from("'defaults' must be an object"))),
+            }
+        }
+
+        Ok(())
+    }
+
+    /*
+        Validate the fields of a state object before it's sent
+    */
+    fn validate_state(&self, state: &Map<String, Value>) -> Result<(), LifxError> {
+        if let Some(color) = state.get("color") {
+            match color.as_str() {
+                Some(color) => self.validate_color(color)?,
+                None => return Err(LifxError::Validation(String::from("'color' must be a string"))),
+            }
+        }
+
+        if let Some(power) = state.get("power") {
+            if !matches!(power.as_str(), Some("on") | Some("off")) {
+                return Err(LifxError::Validation(String::from("'power' should either be 'on' or 'off'")));
+            }
+        }
+
+        for (field, max) in [("brightness", 1.0), ("duration", MAX_DURATION), ("infrared", 1.0)] {
+            if let Some(value) = state.get(field) {
+                check_range(field, value.as_f64().unwrap_or(f64::NAN), 0.0..=max)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /*
+        Check a color string locally, without a round trip to the API
+        https://api.developer.lifx.com/docs/colors
+    */
+    fn validate_color(&self, color: &str) -> Result<(), LifxError> {
+        match color.parse::<Hsbk>() {
+            Ok(_) => Ok(()),
+            Err(color_error) => Err(LifxError::Validation(format!("Unable to parse color '{}'\n  {}", color, color_error.errors.join("\n  ")))),
+        }
+    }
+
+    /*
+        Parse a color string with the API, returning the validation errors if it isn't valid
+        https://api.developer.lifx.com/docs/validate-color
+    */
+    pub async fn color(&self, color: &str) -> Result<Result<ValidateColorResponse, ErrorResponse>, LifxError> {
+        let request = self.client
+            .get(format!("{}{}", self.base_url, "/color"))
+            .query(&[("string", color)]);
+
+        let res = self.execute(request).await?;
+
+        if res.status() == StatusCode::UNPROCESSABLE_ENTITY {
+            return Ok(Err(res.json::<ErrorResponse>().await?));
+        }
+
+        let res = check_status(res).await?;
+
+        Ok(Ok(res.json::<ValidateColorResponse>().await?))
+    }
+
+    /*
+        Send an authenticated request, turning error statuses into a LifxError
+    */
+    async fn send(&self, request: RequestBuilder) -> Result<Response, LifxError> {
+        let res = self.execute(request).await?;
+
+        check_status(res).await
+    }
+
+    /*
+        Send an authenticated request, waiting and trying again when rate limited or the API fails transiently.
+        A server error may come after the request took effect, so only GET and PUT, which are safe to repeat, are retried then
+        https://api.developer.lifx.com/docs/rate-limits
+    */
+    async fn execute(&self, request: RequestBuilder) -> Result<Response, LifxError> {
+        let mut request = request.bearer_auth(&self.token).build()?;
+        let idempotent = matches!(*request.method(), Method::GET | Method::PUT);
+        let mut attempt = 1;
+        let mut waited = Duration::ZERO;
+
+        loop {
+            /* Requests with a streamed body can't be cloned, and are only sent once */
+            let next = match attempt < MAX_ATTEMPTS {
+                true => request.try_clone(),
+                false => None,
+            };
+
+            let res = self.client.execute(request).await?;
+
+            let quota = rate_limit(res.headers());
+
+            if self.verbose {
+                match &quota {
+                    Some(quota) => eprintln!(
+                        "{} {} ({}/{} requests remaining, resets in {}s)",
+                        res.status(), res.url(), quota.remaining, quota.limit, quota.reset.saturating_sub(now()),
+                    ),
+                    None => eprintln!("{} {}", res.status(), res.url()),
+                }
+            }
+
+            let (next, delay) = match (next, retry_delay(&res, attempt, idempotent)) {
+                (Some(next), Some(delay)) if waited + delay <= MAX_RETRY_WAIT => (next, delay),
+                _ => return Ok(res),
+            };
+
+            if self.verbose {
+                eprintln!("Retrying in {:.1}s (attempt {} of {})", delay.as_secs_f64(), attempt + 1, MAX_ATTEMPTS);
+            } else {
+                debug!("{} from {}, retrying in {:?}", res.status(), res.url(), delay);
+            }
+
+            tokio::time::sleep(delay).await;
+
+            waited += delay;
+            request = next;
+            attempt += 1;
+        }
+    }
+}
+
+/*
+    How long to wait before trying again, or None if the response shouldn't be retried.
+    Rate limited requests wait for the quota to reset, transient server errors on idempotent requests back off exponentially
+*/
+fn retry_delay(res: &Response, attempt: u32, idempotent: bool) -> Option<Duration> {
+    let backoff = BASE_BACKOFF * 2u32.pow(attempt - 1);
+
+    let delay = match res.status().as_u16() {
+        429 => match header_u64(res.headers(), "X-RateLimit-Reset") {
+            Some(reset) if reset > now() => Duration::from_secs(reset - now()),
+            _ => backoff,
+        },
+        500 | 502 | 503 | 504 | 523 if idempotent => backoff,
+        _ => return None,
+    };
+
+    /* Jitter so concurrent invocations don't retry in lockstep */
+    Some(delay + delay.mul_f64(fastrand::f64() * 0.5))
+}
+
+fn rate_limit(headers: &HeaderMap) -> Option<RateLimit> {
+    Some(RateLimit {
+        limit: header_u64(headers, "X-RateLimit-Limit")?,
+        remaining: header_u64(headers, "X-RateLimit-Remaining")?,
+        reset: header_u64(headers, "X-RateLimit-Reset")?,
+    })
+}
+
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.parse::<u64>().ok()
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default()
+}
+
+/*
+    Check a number is within range before it's sent. NaN never is
+*/
+fn check_range(field: &str, value: f64, range: RangeInclusive<f64>) -> Result<f64, LifxError> {
+    match range.contains(&value) {
+        true => Ok(value),
+        false => Err(LifxError::Validation(format!("'{}' must be between {:.1} and {:.1}", field, range.start(), range.end()))),
+    }
+}
+
+/*
+    Check a number is greater than 0 and finite before it's sent
+*/
+fn positive(field: &str, value: f64) -> Result<f64, LifxError> {
+    match value.is_finite() && value > 0.0 {
+        true => Ok(value),
+        false => Err(LifxError::Validation(format!("'{}' must be greater than 0.0", field))),
+    }
+}
+
+/*
+    A number for a request body. NaN and infinity have no JSON representation
+*/
+fn number(field: &str, value: f64) -> Result<Value, LifxError> {
+    Number::from_f64(value)
+        .map(Value::Number)
+        .ok_or_else(|| LifxError::Validation(format!("'{}' must be a finite number", field)))
+}
+
+/*
+    https://api.developer.lifx.com/docs/errors
+*/
+async fn check_status(res: Response) -> Result<Response, LifxError> {
+    let status = res.status();
+
+    if status.is_success() {
+        return Ok(res);
+    }
+
+    let reset = header_u64(res.headers(), "X-RateLimit-Reset");
+
+    let errors = res.json::<ErrorResponse>().await.unwrap_or_else(|_| ErrorResponse {
+        error: status.canonical_reason().unwrap_or("Unknown error").to_owned(),
+        errors: vec![],
+    });
+
+    debug!("{} {:?}", status, errors);
+
+    Err(match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => LifxError::Unauthorized,
+        StatusCode::TOO_MANY_REQUESTS => LifxError::RateLimited { reset },
+        StatusCode::NOT_FOUND => LifxError::NotFound(errors.error),
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => LifxError::Validation(describe_errors(&errors)),
+        _ => LifxError::Api { status: status.as_u16(), message: errors.error },
+    })
+}
+
+/*
+    Decode the results of a change. Fast changes are accepted without waiting for the lights, and the API answers
+    202 with no body, so there are no results to report
+*/
+async fn results<T: DeserializeOwned + Default>(res: Response) -> Result<T, LifxError> {
+    if res.status() == StatusCode::ACCEPTED {
+        return Ok(T::default());
+    }
+
+    let body = res.bytes().await?;
+
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(T::default());
+    }
+
+    serde_json::from_slice(&body).map_err(|error| LifxError::Parse(format!("the API response: {}", error)))
+}
+
+/*
+    Parse a color string with the local parser, shaped like the API's validate-color response
+*/
+pub fn parse_color_offline(color: &str) -> Result<ValidateColorResponse, ErrorResponse> {
+    match color.parse::<Hsbk>() {
+        Ok(hsbk) => Ok(ValidateColorResponse {
+            hue: hsbk.hue,
+            saturation: hsbk.saturation,
+            brightness: hsbk.brightness,
+            kelvin: hsbk.kelvin,
+        }),
+        Err(color_error) => Err(ErrorResponse {
+            error: String::from("Validation error"),
+            errors: vec![Error { field: String::from("string"), message: color_error.errors }],
+        }),
+    }
+}
+
+/*
+    The error and each field's problems on their own line
+*/
+pub fn describe_errors(errors: &ErrorResponse) -> String {
+    let mut description = errors.error.clone();
+    for error in &errors.errors {
+        description.push_str(&format!("\n  {}: {}", error.field, error.message.join(", ")));
+    }
+    description
+}
+
+/*
+    Read a JSON or YAML file holding 'states' and optional 'defaults'. A bare list is treated as the states
+*/
+pub fn read_states_file(path: &str) -> Result<Map<String, Value>, LifxError> {
+    let contents = std::fs::read_to_string(path)?;
+
+    let file: Value = if path.ends_with(".yaml") || path.ends_with(".yml") {
+        serde_yaml::from_str(&contents).map_err(|error| LifxError::Parse(format!("{}: {}", path, error)))?
+    } else {
+        serde_json::from_str(&contents).map_err(|error| LifxError::Parse(format!("{}: {}", path, error)))?
+    };
+
+    match file {
+        Value::Object(body) => Ok(body),
+        Value::Array(states) => {
+            let mut body = Map::new();
+            body.insert("states".to_string(), Value::Array(states));
+            Ok(body)
+        },
+        _ => Err(LifxError::Validation(format!("{} should contain an object with 'states' or a list of states", path))),
+    }
+}
@@ -0,0 +1,200 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use serde_derive::{Deserialize, Serialize};
+
+/*
+    Offline parser for the LIFX color string grammar
+    https://api.developer.lifx.com/docs/colors
+*/
+
+/* Named colors set the hue and full saturation, except white which only clears the saturation */
+const NAMED_COLORS: [(&str, f64); 8] = [
+    ("red", 0.0),
+    ("orange", 36.0),
+    ("yellow", 60.0),
+    ("green", 120.0),
+    ("cyan", 180.0),
+    ("blue", 250.0),
+    ("purple", 280.0),
+    ("pink", 325.0),
+];
+
+pub const MIN_KELVIN: f64 = 1500.0;
+pub const MAX_KELVIN: f64 = 9000.0;
+
+/*
+    The components a color string sets. Components it doesn't mention are left as they are on the light
+*/
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Hsbk {
+    pub hue: Option<f64>,
+    pub saturation: Option<f64>,
+    pub brightness: Option<f64>,
+    pub kelvin: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorError {
+    pub errors: Vec<String>,
+}
+
+impl fmt::Display for ColorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.errors.join("; "))
+    }
+}
+
+impl Error for ColorError {}
+
+impl FromStr for Hsbk {
+    type Err = ColorError;
+
+    /*
+        Parse a space separated list of components. Later components override earlier ones
+    */
+    fn from_str(color: &str) -> Result<Hsbk, ColorError> {
+        let mut hsbk = Hsbk::default();
+        let mut errors = vec![];
+
+        for component in color.split_whitespace() {
+            if let Err(error) = hsbk.apply(&component.to_lowercase()) {
+                errors.push(error);
+            }
+        }
+
+        if color.trim().is_empty() {
+            errors.push("color string is empty".to_owned());
+        }
+
+        match errors.is_empty() {
+            true => Ok(hsbk),
+            false => Err(ColorError { errors }),
+        }
+    }
+}
+
+impl Hsbk {
+    fn apply(&mut self, component: &str) -> Result<(), String> {
+        if component == "white" {
+            self.saturation = Some(0.0);
+            return Ok(());
+        }
+
+        if let Some((_, hue)) = NAMED_COLORS.iter().find(|(name, _)| *name == component) {
+            self.hue = Some(*hue);
+            self.saturation = Some(1.0);
+            return Ok(());
+        }
+
+        if let Some(hex) = component.strip_prefix('#') {
+            return match (hex.len(), u32::from_str_radix(hex, 16)) {
+                (6, Ok(rgb)) => {
+                    self.set_rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8);
+                    Ok(())
+                },
+                _ => Err(format!("'{}' is not a valid hex color, expected #RRGGBB", component)),
+            };
+        }
+
+        let (key, value) = component
+            .split_once(':')
+            .ok_or_else(|| format!("'{}' is not a known color or component", component))?;
+
+        let number = |min: f64, max: f64| {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|number| (min..=max).contains(number))
+                .ok_or_else(|| format!("'{}' must be a number between {} and {}", key, min, max))
+        };
+
+        match key {
+            "hue" => self.hue = Some(number(0.0, 360.0)?),
+            "saturation" => self.saturation = Some(number(0.0, 1.0)?),
+            "brightness" => self.brightness = Some(number(0.0, 1.0)?),
+            "kelvin" => {
+                self.kelvin = Some(number(MIN_KELVIN, MAX_KELVIN)?);
+                self.saturation = Some(0.0);
+            },
+            "rgb" => {
+                let channels = value
+                    .split(',')
+                    .map(|channel| channel.parse::<u8>())
+                    .collect::<Result<Vec<u8>, _>>();
+
+                match channels.as_deref() {
+                    Ok([r, g, b]) => self.set_rgb(*r, *g, *b),
+                    _ => return Err(format!("'{}' is not a valid rgb color, expected rgb:[0-255],[0-255],[0-255]", component)),
+                }
+            },
+            _ => return Err(format!("'{}' is not a known color component", key)),
+        }
+
+        Ok(())
+    }
+
+    fn set_rgb(&mut self, r: u8, g: u8, b: u8) {
+        let (r, g, b) = (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0);
+
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+
+        self.hue = Some(hue);
+        self.saturation = Some(if max == 0.0 { 0.0 } else { delta / max });
+        self.brightness = Some(max);
+    }
+
+    /*
+        Approximate RGB value, e.g. for a terminal swatch. Unsaturated colors are tinted by their kelvin when one is set
+        https://tannerhelland.com/2012/09/18/convert-temperature-rgb-algorithm-code.html
+    */
+    pub fn to_rgb(&self) -> (u8, u8, u8) {
+        let hue = self.hue.unwrap_or(0.0);
+        let saturation = self.saturation.unwrap_or(0.0);
+        let brightness = self.brightness.unwrap_or(1.0);
+
+        let white = match self.kelvin {
+            Some(kelvin) => {
+                let temperature = kelvin / 100.0;
+
+                (
+                    if temperature <= 66.0 { 255.0 } else { 329.698727446 * (temperature - 60.0).powf(-0.1332047592) },
+                    if temperature <= 66.0 { 99.4708025861 * temperature.ln() - 161.1195681661 } else { 288.1221695283 * (temperature - 60.0).powf(-0.0755148492) },
+                    if temperature >= 66.0 { 255.0 } else if temperature <= 19.0 { 0.0 } else { 138.5177312231 * (temperature - 10.0).ln() - 305.0447927307 },
+                )
+            },
+            None => (255.0, 255.0, 255.0),
+        };
+
+        let sector = hue.rem_euclid(360.0) / 60.0;
+        let x = 1.0 - (sector % 2.0 - 1.0).abs();
+
+        let pure = match sector as u8 {
+            0 => (1.0, x, 0.0),
+            1 => (x, 1.0, 0.0),
+            2 => (0.0, 1.0, x),
+            3 => (0.0, x, 1.0),
+            4 => (x, 0.0, 1.0),
+            _ => (1.0, 0.0, x),
+        };
+
+        /* Blend between the white point and the fully saturated hue */
+        let channel = |white: f64, pure: f64| {
+            let white = white.clamp(0.0, 255.0);
+            ((white + (pure * 255.0 - white) * saturation) * brightness).round().clamp(0.0, 255.0) as u8
+        };
+
+        (channel(white.0, pure.0), channel(white.1, pure.1), channel(white.2, pure.2))
+    }
+}
@@ -0,0 +1,327 @@
+use async_trait::async_trait;
+use log::debug;
+
+use crate::{cloud, error::LifxError, lan};
+use crate::types::{ListLightResponse, Result as LightResult, SetStateResponse, ToggledLightsResponse};
+
+/*
+    A change to the power and color of lights. Fields that aren't set are left as they are
+*/
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct LightState {
+    pub power: Option<String>,
+    pub color: Option<String>,
+    pub brightness: Option<f64>,
+    /* Seconds to spend making the change */
+    pub duration: Option<f64>,
+    pub infrared: Option<f64>,
+    /* Skip the cloud API's state checks and don't wait for results. LAN writes are always sent this way */
+    pub fast: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WaveformKind {
+    Breathe,
+    Pulse,
+}
+
+/*
+    An effect that switches lights between two colors
+    https://api.developer.lifx.com/docs/breathe-effect
+    https://api.developer.lifx.com/docs/pulse-effect
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waveform {
+    pub kind: WaveformKind,
+    pub color: String,
+    /* Defaults to the current color of each light */
+    pub from_color: Option<String>,
+    /* Seconds per cycle */
+    pub period: Option<f64>,
+    pub cycles: Option<f64>,
+    /* Stay on the last color instead of returning to the first */
+    pub persist: bool,
+    pub power_on: Option<bool>,
+    /* Where in the period the color peaks, between 0.0 and 1.0. Breathe only */
+    pub peak: Option<f64>,
+}
+
+/*
+    The commands every transport supports, so the same command line can run over the cloud API or the LAN
+*/
+#[async_trait]
+pub trait LightController: Send + Sync {
+    async fn list(&self, selector: &str) -> Result<Vec<ListLightResponse>, LifxError>;
+
+    async fn set_state(&self, selector: &str, state: &LightState) -> Result<SetStateResponse, LifxError>;
+
+    /* Turns every matching light off if any of them are on, otherwise turns them all on */
+    async fn toggle(&self, selector: &str, duration: Option<f64>) -> Result<ToggledLightsResponse, LifxError>;
+
+    async fn waveform(&self, selector: &str, waveform: &Waveform) -> Result<SetStateResponse, LifxError>;
+
+    /*
+        Send the state again to just the lights that timed out, up to `retries` times, replacing their statuses
+        with the latest ones so the results read as a single request. `verbose` reports each attempt on stderr
+    */
+    async fn retry_timed_out(&self, results: &mut [LightResult], state: &LightState, retries: u32, verbose: bool) -> Result<(), LifxError> {
+        for attempt in 1..=retries {
+            let timed_out = results
+                .iter()
+                .filter(|result| result.status.as_deref() == Some("timed_out"))
+                .filter_map(|result| result.id.as_ref())
+                .map(|id| format!("id:{}", id))
+                .collect::<Vec<String>>();
+
+            if timed_out.is_empty() {
+                break;
+            }
+
+            if verbose {
+                eprintln!("Retrying {} timed out light/s (attempt {} of {})", timed_out.len(), attempt, retries);
+            } else {
+                debug!("Retrying {} timed out light/s (attempt {} of {})", timed_out.len(), attempt, retries);
+            }
+
+            let retried = self.set_state(&timed_out.join(","), state).await?;
+
+            for retried in retried.results {
+                if let Some(result) = results.iter_mut().find(|result| result.id.is_some() && result.id == retried.id) {
+                    result.status = retried.status;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LightController for cloud::Client {
+    async fn list(&self, selector: &str) -> Result<Vec<ListLightResponse>, LifxError> {
+        self.list_lights(selector).await
+    }
+
+    async fn set_state(&self, selector: &str, state: &LightState) -> Result<SetStateResponse, LifxError> {
+        self.set_state(selector, state.power.as_ref(), state.color.as_ref(), state.brightness, state.duration, state.infrared, state.fast).await
+    }
+
+    async fn toggle(&self, selector: &str, duration: Option<f64>) -> Result<ToggledLightsResponse, LifxError> {
+        self.toggle_lights(selector, duration).await
+    }
+
+    async fn waveform(&self, selector: &str, waveform: &Waveform) -> Result<SetStateResponse, LifxError> {
+        let Waveform { color, from_color, period, cycles, persist, power_on, peak, .. } = waveform;
+
+        match waveform.kind {
+            WaveformKind::Breathe => self.breathe(selector, color, from_color.as_ref(), *period, *cycles, *persist, *power_on, *peak).await,
+            WaveformKind::Pulse => self.pulse(selector, color, from_color.as_ref(), *period, *cycles, *persist, *power_on).await,
+        }
+    }
+}
+
+#[async_trait]
+impl LightController for lan::Client {
+    async fn list(&self, selector: &str) -> Result<Vec<ListLightResponse>, LifxError> {
+        self.lights(selector, None).await
+    }
+
+    async fn set_state(&self, selector: &str, state: &LightState) -> Result<SetStateResponse, LifxError> {
+        if state.infrared.is_some() {
+            return Err(LifxError::Validation(String::from("'infrared' can't be set over LAN")));
+        }
+
+        self.set_state(selector, None, state.power.as_ref(), state.color.as_ref(), state.brightness, state.duration.unwrap_or(0.0)).await
+    }
+
+    async fn toggle(&self, selector: &str, duration: Option<f64>) -> Result<ToggledLightsResponse, LifxError> {
+        self.toggle(selector, None, duration.unwrap_or(0.0)).await
+    }
+
+    async fn waveform(&self, selector: &str, waveform: &Waveform) -> Result<SetStateResponse, LifxError> {
+        self.waveform(selector, None, waveform).await
+    }
+}
+
+const LAN: &str = "lan";
+const CLOUD: &str = "cloud";
+
+/*
+    Uses the LAN for the lights that answer discovery and the cloud API for the rest,
+    reporting which of them each light was reached by
+*/
+pub struct Auto {
+    lan: lan::Client,
+    cloud: cloud::Client,
+}
+
+/* The lights a selector matches, by how they can be reached */
+struct Route {
+    lan: Vec<ListLightResponse>,
+    /* Every light the cloud API knows about, or why it couldn't be asked */
+    cloud: Result<Vec<ListLightResponse>, LifxError>,
+}
+
+impl Route {
+    /* Lights that didn't answer on the LAN */
+    fn cloud_only(&self) -> Vec<&ListLightResponse> {
+        match &self.cloud {
+            Ok(lights) => lights.iter().filter(|light| !self.lan.iter().any(|found| found.id == light.id)).collect(),
+            Err(_) => vec![],
+        }
+    }
+}
+
+/* A change Auto can split across both transports */
+enum Change<'a> {
+    State(&'a LightState),
+    Waveform(&'a Waveform),
+}
+
+impl Change<'_> {
+    async fn apply(&self, controller: &dyn LightController, selector: &str) -> Result<SetStateResponse, LifxError> {
+        match self {
+            Change::State(state) => controller.set_state(selector, state).await,
+            Change::Waveform(waveform) => controller.waveform(selector, waveform).await,
+        }
+    }
+}
+
+/* Selects exactly the given lights on either transport */
+fn id_selector<'a>(ids: impl Iterator<Item = &'a str>) -> String {
+    ids.map(|id| format!("id:{}", id)).collect::<Vec<String>>().join(",")
+}
+
+/* Whether a LAN result shows the light can't have made the change, either because it failed to send or was refused */
+fn unapplied(result: &LightResult) -> bool {
+    matches!(result.status.as_deref(), Some("failed") | Some("unsupported"))
+}
+
+fn via(transport: &str, result: LightResult) -> LightResult {
+    LightResult { transport: Some(transport.to_string()), ..result }
+}
+
+impl Auto {
+    pub fn new(lan: lan::Client, cloud: cloud::Client) -> Auto {
+        Auto { lan, cloud }
+    }
+
+    /*
+        Discover the lights on the LAN while asking the cloud API for every light the selector matches
+    */
+    async fn route(&self, selector: &str) -> Route {
+        let (lan, cloud) = tokio::join!(self.lan.lights(selector, None), self.cloud.list_lights(selector));
+
+        let lan = lan.unwrap_or_else(|error| {
+            debug!("No lights found on the LAN: {}", error);
+            vec![]
+        });
+
+        debug!("{} light/s found on the LAN", lan.len());
+
+        Route { lan, cloud }
+    }
+
+    /*
+        Make the change over the LAN for the lights found there, then over the cloud API for the rest
+        and for any the LAN command never reached or that refused it. A light that timed out may still have
+        made the change, so it isn't sent again where a repeated waveform or toggle would be visible
+    */
+    async fn apply(&self, route: Route, change: Change<'_>) -> Result<SetStateResponse, LifxError> {
+        let mut results: Vec<LightResult> = vec![];
+
+        if !route.lan.is_empty() {
+            match change.apply(&self.lan, &id_selector(route.lan.iter().map(|light| light.id.as_str()))).await {
+                Ok(response) => results.extend(response.results.into_iter().map(|result| via(LAN, result))),
+                Err(error) => debug!("Unable to change lights over the LAN: {}", error),
+            }
+        }
+
+        let cloud_lights = match route.cloud {
+            Ok(lights) => lights,
+            Err(error) if results.is_empty() => return Err(error),
+            Err(error) => {
+                debug!("Unable to reach the cloud API, only the LAN was used: {}", error);
+                return Ok(SetStateResponse { results });
+            },
+        };
+
+        let pending: Vec<String> = cloud_lights
+            .into_iter()
+            .map(|light| light.id)
+            .filter(|id| !results.iter().any(|result| result.id.as_ref() == Some(id) && !unapplied(result)))
+            .collect();
+
+        if pending.is_empty() {
+            return Ok(SetStateResponse { results });
+        }
+
+        match change.apply(&self.cloud, &id_selector(pending.iter().map(String::as_str))).await {
+            Ok(response) => {
+                for result in response.results.into_iter().filter(|result| result.id.as_ref().is_some_and(|id| pending.contains(id))) {
+                    results.retain(|existing| existing.id != result.id);
+                    results.push(via(CLOUD, result));
+                }
+            },
+            Err(error) if results.is_empty() => return Err(error),
+            Err(error) => debug!("Unable to change lights over the cloud API: {}", error),
+        }
+
+        Ok(SetStateResponse { results })
+    }
+}
+
+#[async_trait]
+impl LightController for Auto {
+    async fn list(&self, selector: &str) -> Result<Vec<ListLightResponse>, LifxError> {
+        let route = self.route(selector).await;
+
+        let cloud_only: Vec<ListLightResponse> = route
+            .cloud_only()
+            .into_iter()
+            .map(|light| ListLightResponse { transport: Some(CLOUD.to_string()), ..light.clone() })
+            .collect();
+
+        match (route.lan.is_empty(), route.cloud) {
+            (true, Err(error)) => Err(error),
+            (_, _) => Ok(route
+                .lan
+                .into_iter()
+                .map(|light| ListLightResponse { transport: Some(LAN.to_string()), ..light })
+                .chain(cloud_only)
+                .collect()),
+        }
+    }
+
+    async fn set_state(&self, selector: &str, state: &LightState) -> Result<SetStateResponse, LifxError> {
+        let route = self.route(selector).await;
+
+        self.apply(route, Change::State(state)).await
+    }
+
+    async fn toggle(&self, selector: &str, duration: Option<f64>) -> Result<ToggledLightsResponse, LifxError> {
+        let route = self.route(selector).await;
+
+        /* Decided once for every light, like the cloud API does, so lights on either path end up in the same state */
+        let on = !route.lan.iter().chain(route.cloud_only()).any(|light| light.power == "on");
+        let power = String::from(if on { "on" } else { "off" });
+
+        let state = LightState { power: Some(power.clone()), duration, ..Default::default() };
+
+        let response = self.apply(route, Change::State(&state)).await?;
+
+        Ok(ToggledLightsResponse {
+            results: response
+                .results
+                .into_iter()
+                .map(|result| LightResult { power: result.succeeded().then(|| power.clone()), ..result })
+                .collect(),
+        })
+    }
+
+    async fn waveform(&self, selector: &str, waveform: &Waveform) -> Result<SetStateResponse, LifxError> {
+        let route = self.route(selector).await;
+
+        self.apply(route, Change::Waveform(waveform)).await
+    }
+}
@@ -0,0 +1,119 @@
+use std::{error::Error, fmt, io, net::AddrParseError, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::color::ColorError;
+
+/*
+    Everything that can go wrong talking to lights over either transport.
+    Each kind exits with its own code so scripts can tell them apart:
+
+        2   Validation      the input was rejected and nothing was sent
+        3   Unauthorized    the API key is missing, invalid or revoked
+        4   NotFound        no lights, devices or scenes matched
+        5   RateLimited     the API rate limit was hit
+        6   PartialFailure  some lights didn't apply the change
+        7   LanTimeout      a device on the LAN didn't answer
+        8   Network         the API couldn't be reached
+        9   Api             the API answered with an unexpected status
+        10  Parse           a response or file couldn't be decoded
+        11  Io              a local file or socket error
+        12  Config          the config file couldn't be read or written
+*/
+#[derive(Debug)]
+pub enum LifxError {
+    Unauthorized,
+    /* `reset` is the unix time the quota resets at, when the API reported it */
+    RateLimited { reset: Option<u64> },
+    Validation(String),
+    NotFound(String),
+    PartialFailure { failed: usize, total: usize },
+    LanTimeout(String),
+    Api { status: u16, message: String },
+    Network(String),
+    Parse(String),
+    Io(io::Error),
+    Config(String),
+}
+
+impl LifxError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            LifxError::Validation(_) => 2,
+            LifxError::Unauthorized => 3,
+            LifxError::NotFound(_) => 4,
+            LifxError::RateLimited { .. } => 5,
+            LifxError::PartialFailure { .. } => 6,
+            LifxError::LanTimeout(_) => 7,
+            LifxError::Network(_) => 8,
+            LifxError::Api { .. } => 9,
+            LifxError::Parse(_) => 10,
+            LifxError::Io(_) => 11,
+            LifxError::Config(_) => 12,
+        }
+    }
+}
+
+impl fmt::Display for LifxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LifxError::Unauthorized => write!(f, "The LIFX API rejected the API key. Run 'lifx auth clear' and enter a new one"),
+            LifxError::RateLimited { reset } => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default();
+
+                match reset {
+                    Some(reset) => write!(f, "Rate limited by the LIFX API, try again in {}s", reset.saturating_sub(now)),
+                    None => write!(f, "Rate limited by the LIFX API, try again later"),
+                }
+            },
+            LifxError::Validation(message) => write!(f, "{}", message),
+            LifxError::NotFound(message) => write!(f, "{}", message),
+            LifxError::PartialFailure { failed, total } => write!(f, "{} of {} lights failed", failed, total),
+            LifxError::LanTimeout(message) => write!(f, "{}", message),
+            LifxError::Api { status, message } => write!(f, "The LIFX API responded with {}: {}", status, message),
+            LifxError::Network(message) => write!(f, "Unable to reach the LIFX API: {}", message),
+            LifxError::Parse(message) => write!(f, "Unable to decode {}", message),
+            LifxError::Io(error) => write!(f, "{}", error),
+            LifxError::Config(message) => write!(f, "Unable to access the config file: {}", message),
+        }
+    }
+}
+
+impl Error for LifxError {}
+
+impl From<io::Error> for LifxError {
+    fn from(error: io::Error) -> LifxError {
+        LifxError::Io(error)
+    }
+}
+
+impl From<reqwest::Error> for LifxError {
+    fn from(error: reqwest::Error) -> LifxError {
+        match error.is_decode() {
+            true => LifxError::Parse(format!("the API response: {}", error)),
+            false => LifxError::Network(error.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for LifxError {
+    fn from(error: serde_json::Error) -> LifxError {
+        LifxError::Parse(format!("JSON: {}", error))
+    }
+}
+
+impl From<bincode::Error> for LifxError {
+    fn from(error: bincode::Error) -> LifxError {
+        LifxError::Parse(format!("a LAN packet: {}", error))
+    }
+}
+
+impl From<ColorError> for LifxError {
+    fn from(error: ColorError) -> LifxError {
+        LifxError::Validation(error.to_string())
+    }
+}
+
+impl From<AddrParseError> for LifxError {
+    fn from(error: AddrParseError) -> LifxError {
+        LifxError::Validation(format!("Invalid IP address: {}", error))
+    }
+}
@@ -0,0 +1,427 @@
+use std::{collections::HashMap, net::{SocketAddr, Ipv4Addr, IpAddr}};
+
+use futures::future::join_all;
+use log::debug;
+use tokio::sync::{Mutex, oneshot};
+
+use crate::{color::Hsbk, controller::{Waveform, WaveformKind}, error::LifxError};
+use crate::types::{Color, LanDevice, LanDeviceState, ListLightResponse, SetStateResponse, ToggledLightsResponse, self};
+
+use packet::{LifxPacket, BinarySerializable, SetColorPayload, SetLightPowerPayload, SetWaveformPayload, LIFX_PORT, WAVEFORM_PULSE, WAVEFORM_SINE};
+use scheduler::{Outcome, SchedulerReport};
+use selector::Selector;
+use service::LanService;
+
+pub mod packet;
+pub mod scheduler;
+pub mod selector;
+pub mod service;
+
+/*
+    Talks to devices directly over UDP on the local network
+    https://lan.developer.lifx.com/docs
+*/
+pub struct Client {
+    service: LanService,
+    known: Mutex<Known>,
+}
+
+/*
+    Devices already described, by address. Discovery takes a second, so the devices found are reused
+    for every selector this client resolves until one of them stops answering
+*/
+#[derive(Default)]
+struct Known {
+    devices: HashMap<SocketAddr, LanDevice>,
+    discovered: bool,
+}
+
+impl Client {
+    /*
+        Outbound commands are limited to `rate` messages per second per device
+    */
+    pub fn new(rate: f64) -> Result<Client, LifxError> {
+        Ok(Client { service: LanService::new(rate)?, known: Mutex::new(Known::default()) })
+    }
+
+    /*
+        Resolve the devices to act on, either the single device at `ip` or every discovered device matching the selector
+    */
+    pub async fn devices(&self, selector: &str, ip: Option<&String>) -> Result<Vec<LanDevice>, LifxError> {
+        debug!("Resolving selector {}", selector);
+
+        let devices = self.matching(&selector.parse::<Selector>()?, ip).await?;
+
+        if devices.is_empty() {
+            return Err(LifxError::NotFound(format!("Could not find any devices matching '{}'", selector)));
+        }
+
+        Ok(devices)
+    }
+
+    /*
+        The known devices matching the selector, describing the device at `ip` or discovering devices the first time
+    */
+    async fn matching(&self, selector: &Selector, ip: Option<&String>) -> Result<Vec<LanDevice>, LifxError> {
+        let mut known = self.known.lock().await;
+
+        if let Some(ip) = ip {
+            let address = SocketAddr::new(IpAddr::V4(ip.parse::<Ipv4Addr>()?), LIFX_PORT);
+
+            let device = match known.devices.get(&address) {
+                Some(device) => device.clone(),
+                None => {
+                    let device = self.service.device_at(address).await?;
+                    known.devices.insert(address, device.clone());
+                    device
+                },
+            };
+
+            return Ok(vec![device]);
+        }
+
+        if !known.discovered {
+            for device in self.service.discover(&Selector::All).await? {
+                if let Some(address) = device.address {
+                    known.devices.insert(address, device);
+                }
+            }
+
+            known.discovered = true;
+        }
+
+        let mut devices: Vec<LanDevice> = known.devices.values().filter(|device| selector.matches(device)).cloned().collect();
+        devices.sort_by(|a, b| a.label.cmp(&b.label));
+
+        /* Look again next time, devices may have joined the network since */
+        if devices.is_empty() {
+            known.discovered = false;
+        }
+
+        Ok(devices)
+    }
+
+    /* A device that stopped answering is described again, and the network searched again, before it's next used */
+    async fn forget(&self, device: &LanDevice) {
+        let mut known = self.known.lock().await;
+
+        if let Some(address) = device.address {
+            known.devices.remove(&address);
+        }
+
+        known.discovered = false;
+    }
+
+    /*
+        Read the state of every device that answers. Unlike `devices`, finding none isn't an error.
+        Only the devices' color and power are queried, discovery only runs again when a device is missing or times out
+    */
+    pub async fn states(&self, selector: &Selector, ip: Option<&String>) -> Result<Vec<LanDeviceState>, LifxError> {
+        let devices = self.matching(selector, ip).await?;
+
+        let mut states = vec![];
+
+        for (device, state) in devices.iter().zip(join_all(devices.iter().map(|device| self.service.state(device))).await) {
+            match state {
+                Ok(state) => states.push(state),
+                Err(error) => {
+                    debug!("Unable to read the state of {}: {}", device.id, error);
+                    self.forget(device).await;
+                },
+            }
+        }
+
+        Ok(states)
+    }
+
+    /*
+        The devices and their current state, shaped like the cloud API's list of lights
+    */
+    pub async fn lights(&self, selector: &str, ip: Option<&String>) -> Result<Vec<ListLightResponse>, LifxError> {
+        let devices = self.devices(selector, ip).await?;
+        let states = self.read_states(&devices).await;
+
+        Ok(devices
+            .into_iter()
+            .map(|device| {
+                let state = states.get(&device.id);
+
+                ListLightResponse {
+                    connected: state.is_some(),
+                    power: state.map(|state| state.power.clone()).unwrap_or_else(|| String::from("off")),
+                    color: state.map(|state| Color { hue: state.hue, saturation: state.saturation, kelvin: state.kelvin as f64 }).unwrap_or_default(),
+                    brightness: state.map(|state| state.brightness).unwrap_or_default(),
+                    id: device.id,
+                    label: device.label,
+                    group: device.group,
+                    location: device.location,
+                    ..Default::default()
+                }
+            })
+            .collect())
+    }
+
+    pub async fn set_power(&self, selector: &str, ip: Option<&String>, power: &str) -> Result<SetStateResponse, LifxError> {
+        let on = match power {
+            "on" => true,
+            "off" => false,
+            _ => return Err(LifxError::Validation(String::from("'power' should either be 'on' or 'off'"))),
+        };
+
+        let devices = self.devices(selector, ip).await?;
+
+        debug!("Setting power of {} device/s to {}", devices.len(), power);
+
+        let deliveries = self.queue(&devices, LifxPacket::SetPower, |_| Some(Box::new(SetLightPowerPayload::new(on, 0))))?;
+
+        Ok(Client::results(&devices, vec![deliveries]).await)
+    }
+
+    /*
+        Change the power and color of devices. Color components that aren't given keep each device's current value
+    */
+    pub async fn set_state(&self, selector: &str, ip: Option<&String>, power: Option<&String>, color: Option<&String>, brightness: Option<f64>, duration: f64) -> Result<SetStateResponse, LifxError> {
+        let on = match power.map(|power| power.as_str()) {
+            Some("on") => Some(true),
+            Some("off") => Some(false),
+            Some(_) => return Err(LifxError::Validation(String::from("'power' should either be 'on' or 'off'"))),
+            None => None,
+        };
+
+        let mut hsbk = color.map(|color| parse_color(color)).transpose()?.unwrap_or_default();
+
+        if let Some(brightness) = brightness {
+            if !(0.0..=1.0).contains(&brightness) {
+                return Err(LifxError::Validation(String::from("'brightness' must be between 0.0 and 1.0")));
+            }
+
+            hsbk.brightness = Some(brightness);
+        }
+
+        let duration = milliseconds("duration", duration)?;
+
+        if on.is_none() && hsbk == Hsbk::default() {
+            return Err(LifxError::Validation(String::from("Nothing to change. Provide at least one of 'power', 'color' or 'brightness'")));
+        }
+
+        let devices = self.devices(selector, ip).await?;
+
+        let mut batches = vec![];
+
+        if hsbk != Hsbk::default() {
+            /* SetColor replaces every component, so the ones not given are read from the device first */
+            let states = self.read_states(&devices).await;
+
+            batches.push(self.queue(&devices, LifxPacket::SetColor, |device| {
+                let (hue, saturation, brightness, kelvin) = merge(&hsbk, states.get(&device.id)?);
+
+                Some(Box::new(SetColorPayload::new(hue, saturation, brightness, kelvin, duration)) as Box<dyn BinarySerializable>)
+            })?);
+        }
+
+        if let Some(on) = on {
+            batches.push(self.queue(&devices, LifxPacket::SetLightPower, |_| Some(Box::new(SetLightPowerPayload::new(on, duration))))?);
+        }
+
+        debug!("Setting state of {} device/s", devices.len());
+
+        Ok(Client::results(&devices, batches).await)
+    }
+
+    /*
+        Like the cloud API, turn every device off if any of them are on, otherwise turn them all on
+    */
+    pub async fn toggle(&self, selector: &str, ip: Option<&String>, duration: f64) -> Result<ToggledLightsResponse, LifxError> {
+        let duration = milliseconds("duration", duration)?;
+
+        let devices = self.devices(selector, ip).await?;
+        let states = self.read_states(&devices).await;
+
+        let on = !states.values().any(|state| state.power == "on");
+        let power = String::from(if on { "on" } else { "off" });
+
+        debug!("Turning {} device/s {}", devices.len(), power);
+
+        let deliveries = self.queue(&devices, LifxPacket::SetLightPower, |_| Some(Box::new(SetLightPowerPayload::new(on, duration))))?;
+
+        let results = Client::results(&devices, vec![deliveries]).await.results;
+
+        Ok(ToggledLightsResponse {
+            results: results
+                .into_iter()
+                .map(|result| types::Result { power: result.succeeded().then(|| power.clone()), ..result })
+                .collect(),
+        })
+    }
+
+    /*
+        Run a breathe or pulse effect. Color components that aren't given keep each device's current value
+        https://lan.developer.lifx.com/docs/waveforms
+    */
+    pub async fn waveform(&self, selector: &str, ip: Option<&String>, waveform: &Waveform) -> Result<SetStateResponse, LifxError> {
+        let color = parse_color(&waveform.color)?;
+        let from_color = waveform.from_color.as_deref().map(parse_color).transpose()?;
+
+        for (field, value) in [("period", waveform.period), ("cycles", waveform.cycles)] {
+            if value.is_some_and(|value| !(value.is_finite() && value > 0.0)) {
+                return Err(LifxError::Validation(format!("'{}' must be greater than 0.0", field)));
+            }
+        }
+
+        if waveform.peak.is_some_and(|peak| !(0.0..=1.0).contains(&peak)) {
+            return Err(LifxError::Validation(String::from("'peak' must be between 0.0 and 1.0")));
+        }
+
+        let period = milliseconds("period", waveform.period.unwrap_or(1.0))?;
+
+        let devices = self.devices(selector, ip).await?;
+        let states = self.read_states(&devices).await;
+
+        let mut batches = vec![];
+
+        if waveform.power_on != Some(false) {
+            batches.push(self.queue(&devices, LifxPacket::SetLightPower, |_| Some(Box::new(SetLightPowerPayload::new(true, 0))))?);
+        }
+
+        if let Some(from_color) = from_color {
+            batches.push(self.queue(&devices, LifxPacket::SetColor, |device| {
+                let (hue, saturation, brightness, kelvin) = merge(&from_color, states.get(&device.id)?);
+
+                Some(Box::new(SetColorPayload::new(hue, saturation, brightness, kelvin, 0)) as Box<dyn BinarySerializable>)
+            })?);
+        }
+
+        /* The breathe effect is a sine wave peaking at `peak`, the pulse effect a square wave with an even duty cycle */
+        let (shape, skew) = match waveform.kind {
+            WaveformKind::Breathe => (WAVEFORM_SINE, waveform.peak.unwrap_or(0.5)),
+            WaveformKind::Pulse => (WAVEFORM_PULSE, 0.5),
+        };
+
+        batches.push(self.queue(&devices, LifxPacket::SetWaveform, |device| {
+            let state = states.get(&device.id)?;
+            let (hue, saturation, brightness, kelvin) = merge(&color, state);
+
+            Some(Box::new(SetWaveformPayload::new(
+                !waveform.persist,
+                hue,
+                saturation,
+                brightness,
+                kelvin,
+                period,
+                waveform.cycles.unwrap_or(1.0) as f32,
+                skew,
+                shape,
+            )) as Box<dyn BinarySerializable>)
+        })?);
+
+        debug!("Running {:?} on {} device/s", waveform.kind, devices.len());
+
+        Ok(Client::results(&devices, batches).await)
+    }
+
+    /*
+        Wait for every queued command to be sent, returning how the rate limit treated them
+    */
+    pub async fn flush(&self) -> SchedulerReport {
+        let report = self.service.flush().await;
+
+        debug!("{:?}", report);
+
+        report
+    }
+
+    /*
+        Read the current state of every device that answers, by ID
+    */
+    async fn read_states(&self, devices: &[LanDevice]) -> HashMap<String, LanDeviceState> {
+        join_all(devices.iter().map(|device| self.service.state(device)))
+            .await
+            .into_iter()
+            .filter_map(|state| state.map_err(|error| debug!("Unable to read device state: {}", error)).ok())
+            .map(|state| (state.id.clone(), state))
+            .collect()
+    }
+
+    /*
+        Queue a command for every device. Devices without a payload are skipped and reported as failed
+    */
+    fn queue<F>(&self, devices: &[LanDevice], packet_type: LifxPacket, payload: F) -> Result<Vec<Option<oneshot::Receiver<Outcome>>>, LifxError>
+    where
+        F: Fn(&LanDevice) -> Option<Box<dyn BinarySerializable>>,
+    {
+        let mut deliveries = vec![];
+
+        for device in devices {
+            deliveries.push(match payload(device) {
+                Some(payload) => Some(self.service.send_command(device, packet_type, payload)?),
+                None => None,
+            });
+        }
+
+        Ok(deliveries)
+    }
+
+    /*
+        Wait for every queued command and build one result per device, reporting the first command that didn't succeed
+    */
+    async fn results(devices: &[LanDevice], batches: Vec<Vec<Option<oneshot::Receiver<Outcome>>>>) -> SetStateResponse {
+        let mut statuses = vec!["ok"; devices.len()];
+
+        for batch in batches {
+            let outcomes = join_all(batch.into_iter().map(|delivery| async move {
+                match delivery {
+                    Some(delivery) => delivery.await.map(|outcome| outcome.status()).unwrap_or("failed"),
+                    None => "failed",
+                }
+            })).await;
+
+            for (status, outcome) in statuses.iter_mut().zip(outcomes) {
+                if *status == "ok" {
+                    *status = outcome;
+                }
+            }
+        }
+
+        SetStateResponse {
+            results: devices
+                .iter()
+                .zip(statuses)
+                .map(|(device, status)| types::Result {
+                    id: Some(device.id.clone()),
+                    label: Some(device.label.clone()),
+                    status: Some(status.to_string()),
+                    power: None,
+                    transport: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn parse_color(color: &str) -> Result<Hsbk, LifxError> {
+    color
+        .parse::<Hsbk>()
+        .map_err(|color_error| LifxError::Validation(format!("Unable to parse color '{}'\n  {}", color, color_error.errors.join("\n  "))))
+}
+
+/*
+    Fill in the color components that aren't given from a device's current state
+*/
+fn merge(hsbk: &Hsbk, state: &LanDeviceState) -> (f64, f64, f64, u16) {
+    (
+        hsbk.hue.unwrap_or(state.hue),
+        hsbk.saturation.unwrap_or(state.saturation),
+        hsbk.brightness.unwrap_or(state.brightness),
+        hsbk.kelvin.map(|kelvin| kelvin.round() as u16).unwrap_or(state.kelvin),
+    )
+}
+
+/*
+    Seconds as the whole milliseconds sent in packets
+*/
+fn milliseconds(field: &str, seconds: f64) -> Result<u32, LifxError> {
+    match (0.0..=u32::MAX as f64 / 1000.0).contains(&seconds) {
+        true => Ok((seconds * 1000.0).round() as u32),
+        false => Err(LifxError::Validation(format!("'{}' must be a positive number of seconds", field))),
+    }
+}
@@ -0,0 +1,385 @@
+use crate::error::LifxError;
+
+use log::debug;
+use serde::{Serialize, Deserialize};
+
+/* UDP port every device listens on */
+pub const LIFX_PORT: u16 = 56700;
+
+/* Size in bytes of the encoded header that precedes every payload */
+pub const HEADER_SIZE: usize = 36;
+
+/* Serialize from self to binary */
+pub trait BinarySerializable: Send + Sync {
+    fn serialize(&self) -> Vec<u8>;
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct Header {
+    /* frame */
+    pub size: u16,
+    pub protocol_addressable_tagged_origin: u16,
+    pub source: u32,
+
+    /* frame address */
+    pub target: [u8; 8],
+    pub reserved1: [u8; 6],
+    pub res_required_ack_required: u8,
+    pub sequence: u8,
+    
+    /* protocol header */
+    pub reserved3: u64,
+    pub packet_type: u16,
+    pub reserved4: u16,
+
+    /* variable length payload follows */
+}
+
+impl Header {
+    pub fn new(sequence: u8, packet_type: u16, mac_address: Option<[u8; 6]>) -> Header {
+        let mut target: [u8; 8] = [0; 8];
+
+        if let Some(mac_address) = mac_address {
+            debug!("Targeting specific mac address: {:?}", &mac_address);
+
+            target[..mac_address.len()].copy_from_slice(&mac_address);
+        }
+
+        /* Packets without a specific target must be tagged so every device processes them */
+        let tagged = match mac_address {
+            Some(_) => 0,
+            None => 0b00100000_00000000,
+        };
+
+        let reserved1: [u8; 6] = [0; 6];
+
+        Header { 
+            size: HEADER_SIZE as u16,
+            protocol_addressable_tagged_origin: 1024 | 0b00010000_00000000 | tagged,
+            source: 2,
+            target,
+            reserved1,
+            res_required_ack_required: 2,
+            sequence,
+            reserved3: 0,
+            packet_type,
+            reserved4: 0,
+        }
+    }
+
+    /* The serial number (mac address) of the device that sent this packet */
+    pub fn serial(&self) -> [u8; 6] {
+        let mut serial: [u8; 6] = [0; 6];
+        serial.copy_from_slice(&self.target[..6]);
+        serial
+    }
+}
+
+/*
+    Encode a header and optional payload into a single datagram
+*/
+pub fn encode_packet(sequence: u8, packet_type: LifxPacket, mac_address: Option<[u8; 6]>, payload: Option<&dyn BinarySerializable>) -> Vec<u8> {
+    let mut header = Header::new(sequence, packet_type as u16, mac_address);
+
+    let mut encoded_payload: Vec<u8> = match payload {
+        Some(payload) => payload.serialize(),
+        None => vec![],
+    };
+
+    header.size = (HEADER_SIZE + encoded_payload.len()) as u16;
+
+    let mut encoded: Vec<u8> = bincode::serialize(&header).unwrap();
+    encoded.append(&mut encoded_payload);
+
+    encoded
+}
+
+/*
+    Split a received datagram into its header and payload
+*/
+pub fn decode_packet(data: &[u8]) -> Result<(Header, &[u8]), LifxError> {
+    if data.len() < HEADER_SIZE {
+        return Err(LifxError::Parse(format!("a LAN packet: too short at {} bytes", data.len())));
+    }
+
+    let header = bincode::deserialize::<Header>(&data[..HEADER_SIZE])?;
+
+    Ok((header, &data[HEADER_SIZE..]))
+}
+
+/*
+    Format a serial number the way the cloud API formats light IDs (e.g. d073d5000001)
+*/
+pub fn format_serial(serial: &[u8]) -> String {
+    serial.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/*
+    Read a null padded string field from a payload
+*/
+pub fn read_label(label: &[u8]) -> String {
+    let end = label.iter().position(|byte| *byte == 0).unwrap_or(label.len());
+    String::from_utf8_lossy(&label[..end]).to_string()
+}
+
+/*
+    Round a value converted from the wire format to two decimal places
+*/
+pub fn round(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/* https://lan.developer.lifx.com/docs/changing-a-device#setcolor---packet-102 */
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct SetColorPayload {
+    pub reserved1: u8,
+    pub hue: u16,
+    pub saturation: u16,
+    pub brightness: u16,
+    pub kelvin: u16,
+    pub duration: u32,
+}
+
+impl SetColorPayload {
+    /*
+        Hue is in degrees, saturation and brightness between 0.0 and 1.0 and duration in milliseconds
+    */
+    pub fn new(hue: f64, saturation: f64, brightness: f64, kelvin: u16, duration: u32) -> SetColorPayload {
+        SetColorPayload {
+            reserved1: 0,
+            hue: (hue.rem_euclid(360.0) / 360.0 * 65535.0).round() as u16,
+            saturation: (saturation.clamp(0.0, 1.0) * 65535.0).round() as u16,
+            brightness: (brightness.clamp(0.0, 1.0) * 65535.0).round() as u16,
+            kelvin,
+            duration,
+        }
+    }
+}
+
+impl BinarySerializable for SetColorPayload {
+    fn serialize(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap()
+    }
+}
+
+/* https://lan.developer.lifx.com/docs/waveforms#setwaveform---packet-103 */
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct SetWaveformPayload {
+    pub reserved1: u8,
+    /* Return to the original color once the waveform finishes */
+    pub transient: u8,
+    pub hue: u16,
+    pub saturation: u16,
+    pub brightness: u16,
+    pub kelvin: u16,
+    pub period: u32,
+    pub cycles: f32,
+    pub skew_ratio: i16,
+    pub waveform: u8,
+}
+
+pub const WAVEFORM_SINE: u8 = 1;
+pub const WAVEFORM_PULSE: u8 = 4;
+
+impl SetWaveformPayload {
+    /*
+        Color as for SetColorPayload, period in milliseconds and skew between 0.0 and 1.0
+    */
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(transient: bool, hue: f64, saturation: f64, brightness: f64, kelvin: u16, period: u32, cycles: f32, skew: f64, waveform: u8) -> SetWaveformPayload {
+        let color = SetColorPayload::new(hue, saturation, brightness, kelvin, 0);
+
+        SetWaveformPayload {
+            reserved1: 0,
+            transient: transient as u8,
+            hue: color.hue,
+            saturation: color.saturation,
+            brightness: color.brightness,
+            kelvin,
+            period,
+            cycles,
+            skew_ratio: (skew.clamp(0.0, 1.0) * 65535.0 - 32768.0).round() as i16,
+            waveform,
+        }
+    }
+}
+
+impl BinarySerializable for SetWaveformPayload {
+    fn serialize(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap()
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct StateServiceResponse {
+    pub service: u8,
+    pub port: u32,
+}
+
+/* https://lan.developer.lifx.com/docs/information-messages#statelabel---packet-25 */
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct StateLabelResponse {
+    pub label: [u8; 32],
+}
+
+/*
+    https://lan.developer.lifx.com/docs/information-messages#stategroup---packet-53
+    https://lan.developer.lifx.com/docs/information-messages#statelocation---packet-50
+*/
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct StateGroupingResponse {
+    pub id: [u8; 16],
+    pub label: [u8; 32],
+    pub updated_at: u64,
+}
+
+/* https://lan.developer.lifx.com/docs/information-messages#statepower---packet-22 */
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct StatePowerResponse {
+    pub level: u16,
+}
+
+/* https://lan.developer.lifx.com/docs/information-messages#lightstate---packet-107 */
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct LightStateResponse {
+    pub hue: u16,
+    pub saturation: u16,
+    pub brightness: u16,
+    pub kelvin: u16,
+    pub reserved1: [u8; 2],
+    pub power: u16,
+    pub label: [u8; 32],
+    pub reserved2: [u8; 8],
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct SetLightPowerPayload {
+    pub level: u16,
+    pub duration: u32,
+}
+
+impl SetLightPowerPayload {
+    pub fn new(on: bool, duration: u32) -> SetLightPowerPayload {
+        match on {
+            true => SetLightPowerPayload { level: 65535, duration },
+            false => SetLightPowerPayload { level: 0, duration },
+        }
+    }
+}
+
+impl BinarySerializable for SetLightPowerPayload {
+    fn serialize(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap()
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LifxPacket {
+    /*
+        Query packets
+    */
+
+    /* https://lan.developer.lifx.com/docs/querying-the-device-for-data#discovery */
+    GetService = 2,
+
+    /* https://lan.developer.lifx.com/docs/querying-the-device-for-data#device */
+    GetHostFirware = 14,
+    GetWifiInfo = 16,
+    GetWifiFirmware = 18,
+    GetPower = 20,
+    GetLabel = 23,
+    GetVersion = 32,
+    GetInfo = 34,
+    GetLocation = 48,
+    GetGroup = 51,
+    EchoRequest = 58,
+
+    /* https://lan.developer.lifx.com/docs/querying-the-device-for-data#light */
+    GetColor = 101,
+    GetLightPower = 116,
+    GetInfrared = 120,
+    GetHevCycle = 142,
+    GetHevCycleConfiguration = 145,
+    GetLastHevCycleResult = 148,
+
+    /* https://lan.developer.lifx.com/docs/querying-the-device-for-data#multizone */
+    GetColorZones = 502,
+    GetMultiZoneEffect = 507,
+    GetExtendedColorZones = 511,
+
+    /* https://lan.developer.lifx.com/docs/querying-the-device-for-data#relay */
+    GetRPower = 816,
+
+    /* https://lan.developer.lifx.com/docs/querying-the-device-for-data#tile */
+    GetDeviceChain = 701,
+    Get64 = 707,
+    GetTileEffect = 718,
+
+    /* 
+        Change packets
+    */
+
+    /* https://lan.developer.lifx.com/docs/changing-a-device#device */
+    SetPower = 21,
+    SetLabel = 24,
+    SetReboot = 38,
+    SetLocation = 49,
+    SetGroup = 52,
+
+    /* https://lan.developer.lifx.com/docs/changing-a-device#light */
+    SetColor = 102,
+    SetWaveform = 103,
+    SetLightPower = 117,
+    SetWaveformOptional = 119,
+    SetInfrared = 122,
+    SetHevCycle = 143,
+    SetHevCycleConfiguration = 146,
+
+    /* https://lan.developer.lifx.com/docs/changing-a-device#multizone */
+    SetColorZones = 501,
+    SetMultiZoneEffect = 508,
+    SetExtendedColorZones = 510,
+
+    /* https://lan.developer.lifx.com/docs/changing-a-device#relay */
+    SetRPower = 817,
+
+    /* https://lan.developer.lifx.com/docs/changing-a-device#tile */
+    SetUserPosition = 703,
+    Set64 = 715,
+    SetTileEffect = 719,
+
+    /*
+        Response packets
+    */
+
+    /* https://lan.developer.lifx.com/docs/information-messages */
+    StateService = 3,
+    StatePower = 22,
+    StateLabel = 25,
+    StateLocation = 50,
+    StateGroup = 53,
+    Acknowledgement = 45,
+    LightState = 107,
+    StateUnhandled = 223,
+}
+
+impl LifxPacket {
+    /*
+        Writes that fully replace a piece of device state, so only the latest queued one needs sending
+    */
+    pub fn is_state_write(&self) -> bool {
+        matches!(
+            self,
+            LifxPacket::SetPower
+                | LifxPacket::SetLabel
+                | LifxPacket::SetLocation
+                | LifxPacket::SetGroup
+                | LifxPacket::SetColor
+                | LifxPacket::SetLightPower
+                | LifxPacket::SetInfrared
+                | LifxPacket::SetHevCycle
+                | LifxPacket::SetExtendedColorZones
+        )
+    }
+}
@@ -0,0 +1,291 @@
+use std::{collections::{HashMap, VecDeque}, net::SocketAddr, sync::{Arc, Mutex}, time::Duration};
+
+use log::debug;
+use serde_derive::Serialize;
+use tokio::{net::UdpSocket, sync::oneshot, task::JoinHandle, time::{Instant, sleep_until, timeout_at}};
+
+use super::packet::{LifxPacket, self};
+use crate::error::LifxError;
+
+/* LIFX recommends sending no more than 20 messages per second to a single device */
+pub const DEFAULT_RATE: f64 = 20.0;
+
+const ACK_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/* What eventually happened to a packet handed to the scheduler */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Acknowledged,
+    /* The device answered a query, with the whole response packet */
+    Responded(Vec<u8>),
+    TimedOut,
+    /* The device answered with StateUnhandled */
+    Unsupported,
+    /* Replaced by a newer write of the same kind before it was sent */
+    Coalesced,
+    Failed(String),
+}
+
+impl Outcome {
+    /* Status reported per device, matching the statuses used by the cloud API where they overlap */
+    pub fn status(&self) -> &'static str {
+        match self {
+            Outcome::Acknowledged | Outcome::Responded(_) => "ok",
+            Outcome::TimedOut => "timed_out",
+            Outcome::Unsupported => "unsupported",
+            Outcome::Coalesced => "superseded",
+            Outcome::Failed(_) => "failed",
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchedulerReport {
+    pub sent: usize,
+    pub queried: usize,
+    pub acknowledged: usize,
+    pub timed_out: usize,
+    pub unsupported: usize,
+    pub coalesced: usize,
+    pub delayed: usize,
+    pub failed: usize,
+    pub max_delay_ms: u128,
+}
+
+struct Outbound {
+    packet_type: LifxPacket,
+    /* The response a query waits for instead of an acknowledgement */
+    response_type: Option<LifxPacket>,
+    data: Vec<u8>,
+    queued_at: Instant,
+    outcome: oneshot::Sender<Outcome>,
+}
+
+#[derive(Default)]
+struct TargetQueue {
+    pending: VecDeque<Outbound>,
+    next_slot: Option<Instant>,
+    /* When the last command allows the next one, so commands held back only by queries aren't reported as delayed */
+    next_command_slot: Option<Instant>,
+    active: bool,
+}
+
+#[derive(Default)]
+struct State {
+    queues: HashMap<SocketAddr, TargetQueue>,
+    report: SchedulerReport,
+}
+
+/*
+    Queues outbound packets per device and sends them no faster than the configured rate
+*/
+pub struct Scheduler {
+    interval: Duration,
+    state: Arc<Mutex<State>>,
+    /* Queue workers and the tasks waiting for acknowledgements */
+    workers: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl Scheduler {
+    /*
+        `rate` is the most packets per second sent to each device
+    */
+    pub fn new(rate: f64) -> Result<Scheduler, LifxError> {
+        if !(rate.is_finite() && rate > 0.0) {
+            return Err(LifxError::Validation(format!("'rate' must be a number greater than 0, got {}", rate)));
+        }
+
+        Ok(Scheduler {
+            interval: Duration::from_secs_f64(1.0 / rate),
+            state: Arc::new(Mutex::new(State::default())),
+            workers: Arc::new(Mutex::new(vec![])),
+        })
+    }
+
+    /*
+        Queue a packet for a device. A pending write of the same kind to the same device is superseded by this one,
+        which goes to the back of the queue so it isn't sent ahead of packets queued after the write it replaces
+    */
+    pub fn enqueue(&self, address: SocketAddr, packet_type: LifxPacket, data: Vec<u8>) -> oneshot::Receiver<Outcome> {
+        self.push(address, packet_type, None, data)
+    }
+
+    /*
+        Queue a query for a device, resolving with the response of `response_type` once the device answers
+    */
+    pub fn enqueue_query(&self, address: SocketAddr, packet_type: LifxPacket, data: Vec<u8>, response_type: LifxPacket) -> oneshot::Receiver<Outcome> {
+        self.push(address, packet_type, Some(response_type), data)
+    }
+
+    fn push(&self, address: SocketAddr, packet_type: LifxPacket, response_type: Option<LifxPacket>, data: Vec<u8>) -> oneshot::Receiver<Outcome> {
+        let (sender, receiver) = oneshot::channel();
+
+        let outbound = Outbound { packet_type, response_type, data, queued_at: Instant::now(), outcome: sender };
+
+        let mut state = self.state.lock().unwrap();
+        let queue = state.queues.entry(address).or_default();
+
+        let superseded = match packet_type.is_state_write() {
+            true => queue.pending.iter().position(|pending| pending.packet_type == packet_type).and_then(|index| queue.pending.remove(index)),
+            false => None,
+        };
+
+        queue.pending.push_back(outbound);
+
+        let spawn = !queue.active;
+        queue.active = true;
+
+        if let Some(superseded) = superseded {
+            debug!("Coalescing {:?} to {}", packet_type, address);
+
+            let _ = superseded.outcome.send(Outcome::Coalesced);
+            state.report.coalesced += 1;
+        }
+
+        if spawn {
+            let worker = tokio::spawn(Scheduler::run(self.state.clone(), self.workers.clone(), address, self.interval));
+            Scheduler::track(&self.workers, worker);
+        }
+
+        receiver
+    }
+
+    /* Finished tasks are dropped as new ones start, so a long running watch doesn't collect them forever */
+    fn track(workers: &Mutex<Vec<JoinHandle<()>>>, worker: JoinHandle<()>) {
+        let mut workers = workers.lock().unwrap();
+
+        workers.retain(|worker| !worker.is_finished());
+        workers.push(worker);
+    }
+
+    /*
+        Wait for every queued packet to be sent and acknowledged and return what happened to them
+    */
+    pub async fn flush(&self) -> SchedulerReport {
+        loop {
+            let workers: Vec<JoinHandle<()>> = self.workers.lock().unwrap().drain(..).collect();
+
+            if workers.is_empty() {
+                break;
+            }
+
+            for worker in workers {
+                let _ = worker.await;
+            }
+        }
+
+        self.state.lock().unwrap().report.clone()
+    }
+
+    async fn run(state: Arc<Mutex<State>>, workers: Arc<Mutex<Vec<JoinHandle<()>>>>, address: SocketAddr, interval: Duration) {
+        loop {
+            let slot = state.lock().unwrap().queues[&address].next_slot;
+
+            /* Wait before taking the packet so writes arriving meanwhile can still replace it */
+            if let Some(slot) = slot {
+                sleep_until(slot).await;
+            }
+
+            let outbound = {
+                let mut state = state.lock().unwrap();
+                let queue = state.queues.get_mut(&address).unwrap();
+
+                match queue.pending.pop_front() {
+                    Some(outbound) => outbound,
+                    None => {
+                        queue.active = false;
+                        return;
+                    }
+                }
+            };
+
+            let sent_at = Instant::now();
+            let delay = sent_at - outbound.queued_at;
+
+            {
+                let mut state = state.lock().unwrap();
+
+                let queue = state.queues.get_mut(&address).unwrap();
+                let command_slot = queue.next_command_slot;
+
+                queue.next_slot = Some(sent_at + interval);
+
+                /* Queries are paced like commands but reported separately */
+                match outbound.response_type {
+                    Some(_) => state.report.queried += 1,
+                    None => {
+                        queue.next_command_slot = Some(sent_at + interval);
+                        state.report.sent += 1;
+
+                        if command_slot.is_some_and(|slot| slot > outbound.queued_at) {
+                            state.report.delayed += 1;
+                            state.report.max_delay_ms = state.report.max_delay_ms.max(delay.as_millis());
+                        }
+                    },
+                }
+            }
+
+            /* Acknowledgements are awaited separately so a slow device doesn't hold up its queue */
+            let acknowledgement = tokio::spawn(Scheduler::deliver(state.clone(), address, outbound));
+            Scheduler::track(&workers, acknowledgement);
+        }
+    }
+
+    async fn deliver(state: Arc<Mutex<State>>, address: SocketAddr, outbound: Outbound) {
+        let outcome = match Scheduler::transmit(address, &outbound.data, outbound.response_type).await {
+            Ok(outcome) => outcome,
+            Err(error) => Outcome::Failed(error.to_string()),
+        };
+
+        if outbound.response_type.is_none() {
+            let report = &mut state.lock().unwrap().report;
+
+            match outcome {
+                Outcome::Acknowledged | Outcome::Responded(_) => report.acknowledged += 1,
+                Outcome::TimedOut => report.timed_out += 1,
+                Outcome::Unsupported => report.unsupported += 1,
+                Outcome::Coalesced => report.coalesced += 1,
+                Outcome::Failed(_) => report.failed += 1,
+            }
+        }
+
+        let _ = outbound.outcome.send(outcome);
+    }
+
+    /*
+        Send a packet and wait for the device to acknowledge it, or to answer it when it's a query
+    */
+    async fn transmit(address: SocketAddr, data: &[u8], response_type: Option<LifxPacket>) -> Result<Outcome, Box<dyn std::error::Error + Send + Sync>> {
+        let sequence = packet::decode_packet(data).map_err(|error| error.to_string())?.0.sequence;
+
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.send_to(data, address).await?;
+
+        let deadline = Instant::now() + ACK_TIMEOUT;
+        let mut buffer: [u8; 1024] = [0; 1024];
+
+        while let Ok(received) = timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+            let (number_of_bytes, _) = received?;
+
+            let header = match packet::decode_packet(&buffer[..number_of_bytes]) {
+                Ok((header, _)) if header.sequence == sequence => header,
+                _ => continue,
+            };
+
+            match response_type {
+                Some(response_type) if header.packet_type == response_type as u16 => return Ok(Outcome::Responded(buffer[..number_of_bytes].to_vec())),
+                None if header.packet_type == LifxPacket::Acknowledgement as u16 => return Ok(Outcome::Acknowledged),
+                _ => {},
+            }
+
+            if header.packet_type == LifxPacket::StateUnhandled as u16 {
+                return Ok(Outcome::Unsupported);
+            }
+        }
+
+        debug!("No acknowledgement from {} for sequence {}", address, sequence);
+
+        Ok(Outcome::TimedOut)
+    }
+}
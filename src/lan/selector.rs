@@ -0,0 +1,80 @@
+use std::str::FromStr;
+
+use crate::error::LifxError;
+
+use crate::types::LanDevice;
+
+/*
+    Selectors resolved locally against discovered devices, mirroring the cloud grammar
+    https://api.developer.lifx.com/docs/selectors
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selector {
+    All,
+    Id(String),
+    Label(String),
+    GroupId(String),
+    Group(String),
+    LocationId(String),
+    Location(String),
+    /* Comma separated selectors match a device if any of them do */
+    Any(Vec<Selector>),
+}
+
+impl Selector {
+    pub fn matches(&self, device: &LanDevice) -> bool {
+        match self {
+            Selector::All => true,
+            Selector::Id(id) => device.id.eq_ignore_ascii_case(id),
+            Selector::Label(label) => device.label.eq_ignore_ascii_case(label),
+            Selector::GroupId(id) => device.group.id.eq_ignore_ascii_case(id),
+            Selector::Group(name) => device.group.name.eq_ignore_ascii_case(name),
+            Selector::LocationId(id) => device.location.id.eq_ignore_ascii_case(id),
+            Selector::Location(name) => device.location.name.eq_ignore_ascii_case(name),
+            Selector::Any(selectors) => selectors.iter().any(|selector| selector.matches(device)),
+        }
+    }
+
+    fn parse_single(selector: &str) -> Result<Selector, LifxError> {
+        let selector = selector.trim();
+
+        if selector == "all" {
+            return Ok(Selector::All);
+        }
+
+        if selector.contains('|') {
+            return Err(LifxError::Validation(format!("Zone selectors are not supported over LAN: '{}'", selector)));
+        }
+
+        let (kind, value) = match selector.split_once(':') {
+            Some((kind, value)) if !value.is_empty() => (kind, value.to_string()),
+            _ => return Err(LifxError::Validation(format!("Invalid selector '{}'. Expected 'all' or '<type>:<value>'", selector))),
+        };
+
+        match kind {
+            "id" => Ok(Selector::Id(value)),
+            "label" => Ok(Selector::Label(value)),
+            "group_id" => Ok(Selector::GroupId(value)),
+            "group" => Ok(Selector::Group(value)),
+            "location_id" => Ok(Selector::LocationId(value)),
+            "location" => Ok(Selector::Location(value)),
+            _ => Err(LifxError::Validation(format!("Selector type '{}' is not supported over LAN", kind))),
+        }
+    }
+}
+
+impl FromStr for Selector {
+    type Err = LifxError;
+
+    fn from_str(selector: &str) -> Result<Selector, Self::Err> {
+        let mut selectors = selector
+            .split(',')
+            .map(Selector::parse_single)
+            .collect::<Result<Vec<Selector>, Self::Err>>()?;
+
+        match selectors.len() {
+            1 => Ok(selectors.remove(0)),
+            _ => Ok(Selector::Any(selectors)),
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::{io, net::SocketAddr, collections::HashMap, time::Duration, sync::atomic::{AtomicU8, Ordering}};
+
+use futures::future::join_all;
+use crate::error::LifxError;
+use log::debug;
+use tokio::{net::UdpSocket, sync::oneshot, time::{Instant, timeout_at}};
+
+use super::scheduler::{Scheduler, Outcome, SchedulerReport};
+use super::packet::{LifxPacket, BinarySerializable, Header, StateServiceResponse, StateLabelResponse, StateGroupingResponse, StatePowerResponse, LightStateResponse, LIFX_PORT, self};
+use super::selector::Selector;
+use crate::types::{LanDevice, LanDeviceState, Group, Location};
+
+const DISCOVERY_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/* StateService reports one entry per service, only UDP (1) is usable */
+const UDP_SERVICE: u8 = 1;
+
+pub struct LanService {
+    sequence: AtomicU8,
+    scheduler: Scheduler,
+}
+
+impl LanService {
+    /*
+        Outbound commands and queries are limited to `rate` messages per second per device
+    */
+    pub fn new(rate: f64) -> Result<LanService, LifxError> {
+        Ok(LanService { sequence: AtomicU8::new(0), scheduler: Scheduler::new(rate)? })
+    }
+
+    fn next_sequence(&self) -> u8 {
+        self.sequence.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /* Devices we only know by IP address are addressed with an untargeted (tagged) header */
+    fn target(device: &LanDevice) -> Option<[u8; 6]> {
+        match device.serial {
+            [0, 0, 0, 0, 0, 0] => None,
+            serial => Some(serial),
+        }
+    }
+
+    /*
+        Send a UDP query to all devices (broadcast) and collect every response received before the timeout
+    */
+    pub async fn broadcast_query(&self, packet_type: LifxPacket, query: Option<Box<dyn BinarySerializable>>) -> Result<Vec<(Vec<u8>, SocketAddr)>, LifxError> {
+        let packet = packet::encode_packet(self.next_sequence(), packet_type, None, query.as_deref());
+
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.set_broadcast(true)?;
+        socket.send_to(packet.as_slice(), ("255.255.255.255", LIFX_PORT)).await?;
+
+        let deadline = Instant::now() + DISCOVERY_TIMEOUT;
+        let mut buffer: [u8; 1024] = [0; 1024];
+        let mut responses = vec![];
+
+        while let Ok(received) = timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+            let (number_of_bytes, src_addr) = received?;
+
+            debug!("{:?} bytes from {:?}", number_of_bytes, src_addr);
+
+            responses.push((buffer[..number_of_bytes].to_vec(), src_addr));
+        }
+
+        Ok(responses)
+    }
+
+    /*
+        Send a UDP query to a specific device and wait for the matching response.
+        Queries share the per device rate limit with commands
+    */
+    pub async fn query(&self, device: &LanDevice, packet_type: LifxPacket, response_type: LifxPacket) -> Result<(Header, Vec<u8>), LifxError> {
+        let address = device.address.ok_or_else(|| LifxError::NotFound(format!("The address of {} is unknown", device.id)))?;
+
+        let packet = packet::encode_packet(self.next_sequence(), packet_type, LanService::target(device), None);
+
+        let outcome = self.scheduler.enqueue_query(address, packet_type, packet, response_type).await.unwrap_or(Outcome::TimedOut);
+
+        match outcome {
+            Outcome::Responded(data) => {
+                let (header, payload) = packet::decode_packet(&data)?;
+
+                Ok((header, payload.to_vec()))
+            },
+            Outcome::Failed(error) => Err(LifxError::Io(io::Error::other(error))),
+            Outcome::Unsupported => Err(LifxError::Validation(format!("{} doesn't support {:?}", address, packet_type))),
+            _ => Err(LifxError::LanTimeout(format!("Timed out waiting for {:?} from {}", response_type, address))),
+        }
+    }
+
+    /*
+        Fill in the serial, label, group and location of a device
+    */
+    pub async fn describe(&self, device: &mut LanDevice) -> Result<(), LifxError> {
+        let (label, group, location) = tokio::join!(
+            self.query(device, LifxPacket::GetLabel, LifxPacket::StateLabel),
+            self.query(device, LifxPacket::GetGroup, LifxPacket::StateGroup),
+            self.query(device, LifxPacket::GetLocation, LifxPacket::StateLocation),
+        );
+
+        let (header, label) = label?;
+        let label = bincode::deserialize::<StateLabelResponse>(&label)?;
+        let group = bincode::deserialize::<StateGroupingResponse>(&group?.1)?;
+        let location = bincode::deserialize::<StateGroupingResponse>(&location?.1)?;
+
+        device.serial = header.serial();
+        device.id = packet::format_serial(&device.serial);
+        device.label = packet::read_label(&label.label);
+        device.group = Group { id: packet::format_serial(&group.id), name: packet::read_label(&group.label) };
+        device.location = Location { id: packet::format_serial(&location.id), name: packet::read_label(&location.label) };
+
+        Ok(())
+    }
+
+    /*
+        Read the current power, color and label of a device
+    */
+    pub async fn state(&self, device: &LanDevice) -> Result<LanDeviceState, LifxError> {
+        let (light, power) = tokio::join!(
+            self.query(device, LifxPacket::GetColor, LifxPacket::LightState),
+            self.query(device, LifxPacket::GetPower, LifxPacket::StatePower),
+        );
+
+        let light = bincode::deserialize::<LightStateResponse>(&light?.1)?;
+        let power = bincode::deserialize::<StatePowerResponse>(&power?.1)?;
+
+        Ok(LanDeviceState {
+            id: device.id.clone(),
+            label: packet::read_label(&light.label),
+            power: String::from(if power.level > 0 { "on" } else { "off" }),
+            hue: packet::round(light.hue as f64 * 360.0 / 65535.0),
+            saturation: packet::round(light.saturation as f64 / 65535.0),
+            brightness: packet::round(light.brightness as f64 / 65535.0),
+            kelvin: light.kelvin,
+        })
+    }
+
+    /*
+        Describe the single device at a known IP address
+    */
+    pub async fn device_at(&self, address: SocketAddr) -> Result<LanDevice, LifxError> {
+        let mut device = LanDevice { address: Some(address), ..Default::default() };
+
+        self.describe(&mut device).await?;
+
+        Ok(device)
+    }
+
+    /*
+        Discover devices on the network and return the ones matching the selector
+    */
+    pub async fn discover(&self, selector: &Selector) -> Result<Vec<LanDevice>, LifxError> {
+        let mut devices: HashMap<[u8; 6], LanDevice> = HashMap::new();
+
+        for (data, src_addr) in self.broadcast_query(LifxPacket::GetService, None).await? {
+            let (header, payload) = packet::decode_packet(&data)?;
+
+            if header.packet_type != LifxPacket::StateService as u16 {
+                continue;
+            }
+
+            let service = bincode::deserialize::<StateServiceResponse>(payload)?;
+
+            if service.service != UDP_SERVICE {
+                continue;
+            }
+
+            devices.entry(header.serial()).or_insert_with(|| LanDevice {
+                id: packet::format_serial(&header.serial()),
+                serial: header.serial(),
+                address: Some(SocketAddr::new(src_addr.ip(), service.port as u16)),
+                ..Default::default()
+            });
+        }
+
+        let mut devices: Vec<LanDevice> = devices.into_values().collect();
+
+        for result in join_all(devices.iter_mut().map(|device| self.describe(device))).await {
+            if let Err(error) = result {
+                debug!("Unable to describe device: {}", error);
+            }
+        }
+
+        let mut devices: Vec<LanDevice> = devices.into_iter().filter(|device| selector.matches(device)).collect();
+        devices.sort_by(|a, b| a.label.cmp(&b.label));
+
+        Ok(devices)
+    }
+
+    /*
+        Queue a UDP command to a specific device. The receiver resolves once the command was sent or superseded
+    */
+    pub fn send_command(&self, device: &LanDevice, packet_type: LifxPacket, command: Box<dyn BinarySerializable>) -> Result<oneshot::Receiver<Outcome>, LifxError> {
+        let address = device.address.ok_or_else(|| LifxError::NotFound(format!("The address of {} is unknown", device.id)))?;
+
+        let packet = packet::encode_packet(self.next_sequence(), packet_type, LanService::target(device), Some(command.as_ref()));
+
+        Ok(self.scheduler.enqueue(address, packet_type, packet))
+    }
+
+    /*
+        Wait for all queued commands to be sent
+    */
+    pub async fn flush(&self) -> SchedulerReport {
+        self.scheduler.flush().await
+    }
+}
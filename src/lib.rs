@@ -1,5 +1,6 @@
-use prettytable::Table;
-
-pub trait SerializeToTable {
-    fn serialize_row(&self, table: &mut Table);
-}
\ No newline at end of file
+pub mod cloud;
+pub mod color;
+pub mod controller;
+pub mod error;
+pub mod lan;
+pub mod types;
@@ -1,50 +1,33 @@
-use lifx_cli::SerializeToTable;
-use prettytable::{Table, format};
+use lifx_cli::color::Hsbk;
+use lifx_cli::types::{ToggledLightsResponse, Result, SetStateResponse, SetStatesResponse, SceneResponse, ValidateColorResponse, LanDevice, LanDeviceState};
+use prettytable::{Table, Cell, Row, Attr, color, format};
 use ansi_rgb::Background;
 use rgb::RGB8;
-use hsl::HSL;
 
-use super::types::{ListLightResponse, ToggledLightsResponse, SetStateResponse};
-
-impl SerializeToTable for ListLightResponse {
-    fn serialize_row(&self, table: &mut Table) {
-        let hsl = HSL {
-            h: self.color.hue,
-            s: self.color.saturation * 100.0,
-            l: if self.color.saturation == 0.0 { 1.1 } else { 0.5 },
-        };
-
-        let (r, g, b) = hsl.to_rgb();
-
-        table.add_row(row![
-            format!("{}", self.id),
-            format!("{}", self.label),
-            format!("{}", self.connected),
-            format!("{}", self.power),
-            format!("{}%", self.brightness * 100.0),
-            "     ".bg(RGB8::new(r, g, b)),
-        ]);
-    }
+pub trait SerializeToTable {
+    fn serialize_row(&self, table: &mut Table);
 }
 
 impl SerializeToTable for ToggledLightsResponse {
     fn serialize_row(&self, table: &mut Table) {
         table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
 
-        table.add_row(row![
+        let via = reports_transport(&self.results);
+
+        table.add_row(with_transport(row![
             b -> "ID",
             b -> "Status",
             b -> "Label",
             b -> "Power",
-        ]);
+        ], via.then(|| cell!(b -> "Via"))));
 
         for result in &self.results {
-            table.add_row(row![
+            table.add_row(highlight_failure(with_transport(row![
                 result.id.as_ref().unwrap_or(&"".to_owned()),
                 result.status.as_ref().unwrap_or(&"".to_owned()),
                 result.label.as_ref().unwrap_or(&"".to_owned()),
                 result.power.as_ref().unwrap_or(&"".to_owned()),
-            ]);
+            ], via.then(|| cell!(result.transport.as_deref().unwrap_or("")))), result));
         }
     }
 }
@@ -53,18 +36,176 @@ impl SerializeToTable for SetStateResponse {
     fn serialize_row(&self, table: &mut Table) {
         table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
 
-        table.add_row(row![
+        let via = reports_transport(&self.results);
+
+        table.add_row(with_transport(row![
             b -> "ID",
             b -> "Status",
             b -> "Label",
-        ]);
+        ], via.then(|| cell!(b -> "Via"))));
 
         for result in &self.results {
-            table.add_row(row![
+            table.add_row(highlight_failure(with_transport(row![
                 result.id.as_ref().unwrap_or(&"".to_owned()),
                 result.status.as_ref().unwrap_or(&"".to_owned()),
                 result.label.as_ref().unwrap_or(&"".to_owned()),
-            ]);
+            ], via.then(|| cell!(result.transport.as_deref().unwrap_or("")))), result));
+        }
+    }
+}
+
+impl SerializeToTable for SetStatesResponse {
+    fn serialize_row(&self, table: &mut Table) {
+        table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+        table.add_row(row![
+            b -> "ID",
+            b -> "Status",
+            b -> "Label",
+        ]);
+
+        for operation in &self.results {
+            let selector = operation.operation.get("selector").and_then(|selector| selector.as_str()).unwrap_or("");
+
+            table.add_row(Row::new(vec![Cell::new(selector).style_spec("bFc").with_hspan(3)]));
+
+            for result in &operation.results {
+                table.add_row(highlight_failure(row![
+                    result.id.as_ref().unwrap_or(&"".to_owned()),
+                    result.status.as_ref().unwrap_or(&"".to_owned()),
+                    result.label.as_ref().unwrap_or(&"".to_owned()),
+                ], result));
+            }
+        }
+    }
+}
+
+/* Only results that went through more than one transport say which one each light was reached by */
+fn reports_transport(results: &[Result]) -> bool {
+    results.iter().any(|result| result.transport.is_some())
+}
+
+fn with_transport(mut row: Row, transport: Option<Cell>) -> Row {
+    if let Some(transport) = transport {
+        row.add_cell(transport);
+    }
+
+    row
+}
+
+/* Rows of lights that didn't reach the requested state are shown in red */
+fn highlight_failure(mut row: Row, result: &Result) -> Row {
+    if !result.succeeded() {
+        for cell in row.iter_mut() {
+            cell.style(Attr::ForegroundColor(color::RED));
+        }
+    }
+
+    row
+}
+
+/*
+    Count the lights with each status, in the order they first appear, e.g. "7 ok, 2 timed out, 1 offline"
+*/
+pub fn summarize(results: &[&Result]) -> String {
+    /* Fast changes are accepted without waiting to hear back from the lights */
+    if results.is_empty() {
+        return String::from("Accepted, no per-light results were returned");
+    }
+
+    let mut counts: Vec<(&str, usize)> = vec![];
+
+    for result in results {
+        let status = result.status.as_deref().unwrap_or("unknown");
+
+        match counts.iter_mut().find(|(counted, _)| *counted == status) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((status, 1)),
         }
     }
+
+    counts
+        .iter()
+        .map(|(status, count)| format!("{} {}", count, status.replace('_', " ")))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl SerializeToTable for ValidateColorResponse {
+    fn serialize_row(&self, table: &mut Table) {
+        let (r, g, b) = Hsbk {
+            hue: self.hue,
+            saturation: self.saturation,
+            brightness: self.brightness,
+            kelvin: Some(self.kelvin.unwrap_or(6500.0)),
+        }.to_rgb();
+
+        let optional = |value: Option<f64>| value.map(|value| value.to_string()).unwrap_or_else(|| "-".to_owned());
+
+        table.add_row(row![
+            optional(self.hue),
+            optional(self.saturation),
+            optional(self.brightness),
+            optional(self.kelvin),
+            "     ".bg(RGB8::new(r, g, b)),
+        ]);
+    }
+}
+
+impl SerializeToTable for LanDevice {
+    fn serialize_row(&self, table: &mut Table) {
+        table.add_row(row![
+            format!("{}", self.id),
+            format!("{}", self.label),
+            format!("{}", self.group.name),
+            format!("{}", self.location.name),
+            self.address.map(|address| address.to_string()).unwrap_or_default(),
+        ]);
+    }
+}
+
+impl SerializeToTable for LanDeviceState {
+    fn serialize_row(&self, table: &mut Table) {
+        table.add_row(row![
+            format!("{}", self.id),
+            format!("{}", self.label),
+            format!("{}", self.power),
+            format!("{}%", self.brightness * 100.0),
+            format!("{}", self.hue),
+            format!("{}", self.saturation),
+            format!("{}", self.kelvin),
+        ]);
+    }
+}
+
+impl SerializeToTable for SceneResponse {
+    fn serialize_row(&self, table: &mut Table) {
+        table.add_row(row![
+            format!("{}", self.name),
+            format!("{}", self.uuid),
+            format_timestamp(self.updated_at),
+            format!("{}", self.states.len()),
+        ]);
+    }
+}
+
+/*
+    Format seconds since the unix epoch as a UTC date and time
+    http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+*/
+fn format_timestamp(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(86400);
+    let seconds = timestamp.rem_euclid(86400);
+
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, seconds / 3600, seconds % 3600 / 60)
 }
\ No newline at end of file
@@ -1,204 +1,265 @@
-use std::collections::HashMap;
+use super::cli_printables::{SerializeToTable, summarize};
+use super::list_view::ListView;
+use super::output::Output;
 
-use super::types::{ListLightResponse, ToggledLightsResponse, InvalidColorResponse, SetStateResponse};
-
-use lifx_cli::SerializeToTable;
+use lifx_cli::{cloud::{self, Client}, error::LifxError};
+use lifx_cli::controller::{LightController, LightState, Waveform, WaveformKind};
+use lifx_cli::types::LightResults;
 use log::debug;
 use prettytable::{Table, format};
-use reqwest::{Client, StatusCode};
-use serde_json::{Map, Value, Number};
-use urlencoding::encode;
-
-const LIFX_URL_TEMPLATE: &str = "https://api.lifx.com/v1";
+use serde::Serialize;
 
 pub struct LifxCommands {
-    token: String,
-    display_raw: bool,
+    /* Runs the commands every transport supports */
+    controller: Box<dyn LightController>,
+    /* Runs the rest, unless only the LAN is used */
+    client: Option<Client>,
+    output: Output,
+    /* Succeed even when some lights didn't reach the requested state */
+    allow_partial: bool,
+    verbose: bool,
 }
 
 impl LifxCommands {
-    pub fn new(key: &str, raw: &bool) -> LifxCommands {
-        LifxCommands { token: String::from(key), display_raw: *raw }
+    pub fn new(controller: Box<dyn LightController>, client: Option<Client>, output: Output, allow_partial: bool, verbose: bool) -> LifxCommands {
+        LifxCommands { controller, client, output, allow_partial, verbose }
     }
 
-    /*
-        https://api.developer.lifx.com/docs/list-lights
-    */
-    pub async fn list_lights(&self, selector: &String) -> Result<(), Box<dyn std::error::Error>> {
-        let client = Client::new();
-
-        debug!("Sending request");
+    fn cloud(&self, command: &str) -> Result<&Client, LifxError> {
+        self.client
+            .as_ref()
+            .ok_or_else(|| LifxError::Validation(format!("'{}' is only available over the cloud API. Use --transport cloud or auto", command)))
+    }
 
-        let res: reqwest::Response = client
-            .get(format!("{}{}", LIFX_URL_TEMPLATE, "/lights/".to_owned() + encode(selector).into_owned().as_str()))
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+    pub async fn list_lights(&self, selector: &str, view: &ListView) -> Result<(), LifxError> {
+        let lights = view.apply(self.controller.list(selector).await?);
 
-        debug!("Parsing json");
+        self.output.print(&lights, &lights, || view.table(&lights).printstd())
+    }
 
-        let lights = res.json::<Vec<ListLightResponse>>().await?;
+    pub async fn toggle_lights(&self, selector: &str, duration: Option<f64>, retries: u32) -> Result<(), LifxError> {
+        let mut toggle_results = self.controller.toggle(selector, duration).await?;
 
-        debug!("{}", serde_json::to_string_pretty(&lights)?);
+        if retries > 0 {
+            /*
+                Toggling again could flip back a light that did change but didn't confirm it in time. Every toggled light
+                ends up in the same power state, so the ones that timed out are set to match the rest instead
+            */
+            match toggle_results.results.iter().find(|result| result.succeeded()).and_then(|result| result.power.clone()) {
+                Some(power) => {
+                    let state = LightState { power: Some(power), duration, ..Default::default() };
 
-        if self.display_raw {
-            println!("{}", serde_json::to_string_pretty(&lights)?);
-        } else {
-            let mut table = Table::new();
-            table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
-    
-            table.add_row(row![
-                b -> "ID",
-                b -> "Label",
-                b -> "Connected",
-                b -> "Power",
-                b -> "Brightness",
-                b -> "Color",
-            ]);
-    
-            for light in lights {
-                light.serialize_row(&mut table);
+                    self.controller.retry_timed_out(&mut toggle_results.results, &state, retries, self.verbose).await?;
+                },
+                None => debug!("No light reported its new power state, not retrying"),
             }
-    
-            table.printstd();
         }
 
-        Ok(())
+        self.print_results(&toggle_results)
     }
 
-    pub async fn toggle_lights(&self, selector: &String, duration: Option<f64>) -> Result<(), Box<dyn std::error::Error>> {
-        let client = Client::new();
+    #[allow(clippy::too_many_arguments)]
+    pub async fn set_state(
+        &self,
+        selector: &str,
+        power: Option<&String>,
+        color: Option<&String>,
+        brightness: Option<f64>,
+        duration: Option<f64>,
+        infrared: Option<f64>,
+        fast: Option<bool>,
+        retries: u32,
+    ) -> Result<(), LifxError> {
+        let state = LightState { power: power.cloned(), color: color.cloned(), brightness, duration, infrared, fast };
 
-        debug!("Sending request");
+        let mut set_state_response = self.controller.set_state(selector, &state).await?;
 
-        let mut body = HashMap::new();
+        self.controller.retry_timed_out(&mut set_state_response.results, &state, retries, self.verbose).await?;
 
-        if let Some(duration) = duration {
-            debug!("duration: {}", duration.to_string());
-            body.insert("duration".to_string(), duration.to_string());
-        }
+        self.print_results(&set_state_response)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn adjust(
+        &self,
+        selector: &str,
+        power: Option<&String>,
+        duration: Option<f64>,
+        infrared: Option<f64>,
+        hue: Option<f64>,
+        saturation: Option<f64>,
+        brightness: Option<f64>,
+        kelvin: Option<i64>,
+    ) -> Result<(), LifxError> {
+        let set_state_response = self.cloud("adjust")?.adjust(selector, power, duration, infrared, hue, saturation, brightness, kelvin).await?;
+
+        self.print_results(&set_state_response)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn breathe(
+        &self,
+        selector: &str,
+        color: &str,
+        from_color: Option<&String>,
+        period: Option<f64>,
+        cycles: Option<f64>,
+        persist: bool,
+        power_on: Option<bool>,
+        peak: Option<f64>,
+    ) -> Result<(), LifxError> {
+        let waveform = Waveform { kind: WaveformKind::Breathe, color: color.to_string(), from_color: from_color.cloned(), period, cycles, persist, power_on, peak };
+
+        let effect_response = self.controller.waveform(selector, &waveform).await?;
+
+        self.print_results(&effect_response)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn pulse(
+        &self,
+        selector: &str,
+        color: &str,
+        from_color: Option<&String>,
+        period: Option<f64>,
+        cycles: Option<f64>,
+        persist: bool,
+        power_on: Option<bool>,
+    ) -> Result<(), LifxError> {
+        let waveform = Waveform { kind: WaveformKind::Pulse, color: color.to_string(), from_color: from_color.cloned(), period, cycles, persist, power_on, peak: None };
+
+        let effect_response = self.controller.waveform(selector, &waveform).await?;
+
+        self.print_results(&effect_response)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn effect(
+        &self,
+        selector: &str,
+        effect: &str,
+        direction: Option<&String>,
+        period: Option<f64>,
+        cycles: Option<f64>,
+        duration: Option<f64>,
+        palette: Vec<&String>,
+        power_on: Option<bool>,
+        fast: bool,
+    ) -> Result<(), LifxError> {
+        let effect_response = self.cloud("effect")?.effect(selector, effect, direction, period, cycles, duration, palette, power_on, fast).await?;
+
+        self.print_results(&effect_response)
+    }
+
+    pub async fn effects_off(&self, selector: &str, power_off: bool) -> Result<(), LifxError> {
+        let effect_response = self.cloud("effect off")?.effects_off(selector, power_off).await?;
 
-        debug!("{:?}", body);
+        self.print_results(&effect_response)
+    }
+
+    pub async fn set_states(&self, path: &str, fast: bool) -> Result<(), LifxError> {
+        let set_states_response = self.cloud("set-states")?.set_states(cloud::read_states_file(path)?, fast).await?;
+
+        self.print_results(&set_states_response)
+    }
 
-        let res: reqwest::Response = client
-            .post(format!("{}{}", LIFX_URL_TEMPLATE, "/lights/".to_owned() + encode(selector).into_owned().as_str() + "/toggle"))
-            .json(&body)
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+    pub async fn clean(&self, selector: &str, duration: Option<u64>, stop: bool) -> Result<(), LifxError> {
+        let clean_response = self.cloud("clean")?.clean(selector, duration, stop).await?;
 
-        debug!("Light/s toggled");
+        self.print_results(&clean_response)
+    }
 
-        let toggle_results = res.json::<ToggledLightsResponse>().await?;
+    pub async fn list_scenes(&self) -> Result<(), LifxError> {
+        let scenes = self.cloud("scenes")?.list_scenes().await?;
 
-        if self.display_raw {
-            println!("{}", serde_json::to_string_pretty(&toggle_results)?);
-        } else {
+        self.output.print(&scenes, &scenes, || {
             let mut table = Table::new();
-            toggle_results.serialize_row(&mut table);
-            table.printstd();
-        }
+            table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+            table.add_row(row![
+                b -> "Name",
+                b -> "UUID",
+                b -> "Updated",
+                b -> "States",
+            ]);
 
-        Ok(())
+            for scene in &scenes {
+                scene.serialize_row(&mut table);
+            }
+
+            table.printstd();
+        })
     }
 
-    pub async fn set_state(
+    pub async fn activate_scene(
         &self,
-        selector: &String,
-        power: Option<&String>,
-        color: Option<&String>,
-        brightness: Option<f64>,
+        scene: &str,
         duration: Option<f64>,
-        infrared: Option<f64>,
-        fast: Option<bool>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let client = Client::new();
-
-        let mut body = Map::new();
-
-        // Validate the color if that's provided
-        if let Some(color) = color {
-            let client = Client::new();
-
-            let res: reqwest::Response = client
-                .get(format!("{}{}", LIFX_URL_TEMPLATE, "/color"))
-                .query(&[("string", color)])
-                .bearer_auth(&self.token)
-                .send()
-                .await?;
-
-            if res.status() == StatusCode::UNPROCESSABLE_ENTITY {
-                let color_validation_errors = res.json::<InvalidColorResponse>().await?;
-                println!("{}", color_validation_errors.error);
-                for error in color_validation_errors.errors {
-                    println!("{:?}", error);
-                    return Ok(())
-                }
-            }
+        ignore: Vec<&String>,
+        overrides: Vec<&String>,
+        fast: bool,
+    ) -> Result<(), LifxError> {
+        let activate_response = self.cloud("scenes")?.activate_scene(scene, duration, ignore, overrides, fast).await?;
 
-            body.insert("color".to_string(), Value::String(color.to_string()));
-        };
+        self.print_results(&activate_response)
+    }
 
-        if let Some(power) = power {
-            match &power[..] {
-                "on" | "off" => {
-                    body.insert("power".to_string(), Value::String(power.to_string()));
-                },
-                _ => {
-                    println!("'power' should either be 'on' or 'off'");
-                    return Ok(());
-                }
-            }
-        }
+    pub async fn cycle(&self, selector: &str, path: &str, direction: Option<&String>) -> Result<(), LifxError> {
+        let cycle_response = self.cloud("cycle")?.cycle(selector, cloud::read_states_file(path)?, direction).await?;
 
-        if let Some(brightness) = brightness {
-            if brightness < 0.0 && brightness > 1.0 {
-                println!("'brightness' must be between 0.0 and 1.0");
-                return Ok(());
-            }
+        self.print_results(&cycle_response)
+    }
 
-            body.insert("brightness".to_string(), Value::Number(Number::from_f64(brightness).unwrap()));
-        }
+    pub async fn color(&self, color: &str, offline: bool) -> Result<(), LifxError> {
+        let parsed = match offline {
+            true => cloud::parse_color_offline(color),
+            false => self.cloud("color")?.color(color).await?,
+        };
 
-        if let Some(duration) = duration {
-            if duration < 0.0 && duration > 3155760000.0 {
-                println!("'duration' must be between 0.0 and 3155760000.0");
-                return Ok(());
-            }
+        match parsed {
+            Ok(parsed) => self.output.print(&parsed, std::slice::from_ref(&parsed), || {
+                let mut table = Table::new();
+                table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
 
-            body.insert("duration".to_string(), Value::Number(Number::from_f64(duration).unwrap()));
-        }
+                table.add_row(row![
+                    b -> "Hue",
+                    b -> "Saturation",
+                    b -> "Brightness",
+                    b -> "Kelvin",
+                    b -> "Color",
+                ]);
 
-        if let Some(infrared) = infrared {
-            if infrared < 0.0 && infrared > 1.0 {
-                println!("'infrared' must be between 0.0 and 1.0");
-                return Ok(());
-            }
+                parsed.serialize_row(&mut table);
 
-            body.insert("infrared".to_string(), Value::Number(Number::from_f64(infrared).unwrap()));
-        }
+                table.printstd();
+            }),
+            Err(color_validation_errors) => {
+                /* The reasons are in the error message, tables don't repeat them */
+                self.output.print(&color_validation_errors, std::slice::from_ref(&color_validation_errors), || {})?;
 
-        if let Some(fast) = fast {
-            body.insert("fast".to_string(), Value::Bool(fast));
+                Err(LifxError::Validation(cloud::describe_errors(&color_validation_errors)))
+            }
         }
+    }
 
-        let res: reqwest::Response = client
-            .put(format!("{}{}", LIFX_URL_TEMPLATE, "/lights/".to_owned() + encode(selector).into_owned().as_str() + "/state"))
-            .json(&body)
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
-
-        let set_state_response = res.json::<SetStateResponse>().await?;
-
-        if self.display_raw {
-            println!("{}", serde_json::to_string_pretty(&set_state_response)?);
-        } else {
+    /*
+        Print the status of every light, with a summary under tables, then fail if any of them didn't reach the requested state
+    */
+    fn print_results<T>(&self, response: &T) -> Result<(), LifxError>
+    where
+        T: Serialize + SerializeToTable + LightResults,
+    {
+        self.output.print(response, &response.light_results(), || {
             let mut table = Table::new();
-            set_state_response.serialize_row(&mut table);
+            response.serialize_row(&mut table);
             table.printstd();
-        }
 
-        Ok(())
+            println!("{}", summarize(&response.light_results()));
+        })?;
+
+        match self.allow_partial {
+            true => Ok(()),
+            false => response.check(),
+        }
     }
 }
@@ -0,0 +1,204 @@
+use std::{collections::{BTreeMap, HashSet}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use super::cli_printables::{SerializeToTable, summarize};
+use super::output::Output;
+
+use lifx_cli::{error::LifxError, lan::{Client, selector::Selector}};
+use lifx_cli::types::{LanDeviceState, LanStateEvent, LightResults, SetStateResponse};
+use prettytable::{Table, Attr, color, format};
+use serde_json::Value;
+
+pub struct LanCommands {
+    client: Client,
+    output: Output,
+    /* Succeed even when some devices didn't acknowledge the change */
+    allow_partial: bool,
+}
+
+impl LanCommands {
+    pub fn new(output: Output, rate: f64, allow_partial: bool) -> Result<LanCommands, LifxError> {
+        Ok(LanCommands { client: Client::new(rate)?, output, allow_partial })
+    }
+
+    /*
+        Wait for queued commands and warn when the rate limit dropped or held back any of them
+    */
+    async fn flush(&self) {
+        let report = self.client.flush().await;
+
+        if report.coalesced > 0 || report.delayed > 0 {
+            eprintln!(
+                "{} sent, {} delayed by rate limit (max {}ms), {} dropped as superseded",
+                report.sent, report.delayed, report.max_delay_ms, report.coalesced,
+            );
+        }
+    }
+
+    /*
+        Print one result per device and a summary. Fails if any device didn't acknowledge every command
+    */
+    async fn report(&self, results: SetStateResponse) -> Result<(), LifxError> {
+        self.flush().await;
+
+        self.output.print(&results, &results.light_results(), || {
+            let mut table = Table::new();
+            results.serialize_row(&mut table);
+            table.printstd();
+
+            println!("{}", summarize(&results.light_results()));
+        })?;
+
+        match self.allow_partial {
+            true => Ok(()),
+            false => results.check(),
+        }
+    }
+
+    pub async fn discover(&self, selector: &str, ip: Option<&String>) -> Result<(), LifxError> {
+        let devices = self.client.devices(selector, ip).await?;
+
+        self.output.print(&devices, &devices, || {
+            let mut table = Table::new();
+            table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+            table.add_row(row![
+                b -> "ID",
+                b -> "Label",
+                b -> "Group",
+                b -> "Location",
+                b -> "Address",
+            ]);
+
+            for device in &devices {
+                device.serialize_row(&mut table);
+            }
+
+            table.printstd();
+        })
+    }
+
+    pub async fn set_power(&self, selector: &str, ip: Option<&String>, power: &str) -> Result<(), LifxError> {
+        let results = self.client.set_power(selector, ip, power).await?;
+
+        self.report(results).await
+    }
+
+    pub async fn set_state(&self, selector: &str, ip: Option<&String>, power: Option<&String>, color: Option<&String>, brightness: Option<f64>, duration: f64) -> Result<(), LifxError> {
+        let results = self.client.set_state(selector, ip, power, color, brightness, duration).await?;
+
+        self.report(results).await
+    }
+
+    /*
+        Poll devices every `interval` seconds and print whenever their power, color or label change, or they come and go
+    */
+    pub async fn watch(&self, selector: &str, ip: Option<&String>, interval: f64) -> Result<(), LifxError> {
+        let selector: Selector = selector.parse()?;
+
+        /* Last known state of every device seen so far, and whether it answered the latest poll */
+        let mut known: BTreeMap<String, (LanDeviceState, bool)> = BTreeMap::new();
+
+        let mut first = true;
+
+        loop {
+            let states = match self.client.states(&selector, ip).await {
+                Ok(states) => states,
+                Err(error) => {
+                    /* Devices aren't marked offline when the network itself couldn't be used */
+                    eprintln!("Unable to poll devices, trying again in {}s: {}", interval, error);
+                    tokio::time::sleep(Duration::from_secs_f64(interval)).await;
+                    continue;
+                },
+            };
+
+            let mut events: Vec<LanStateEvent> = vec![];
+            let mut seen: HashSet<String> = HashSet::new();
+
+            for state in states {
+                seen.insert(state.id.clone());
+
+                match known.get(&state.id) {
+                    Some((previous, true)) => {
+                        for (field, old, new) in state.changes(previous) {
+                            events.push(LanCommands::event("changed", &state, Some((field, old, new))));
+                        }
+                    },
+                    _ => events.push(LanCommands::event("online", &state, None)),
+                }
+
+                known.insert(state.id.clone(), (state, true));
+            }
+
+            for (id, (state, online)) in known.iter_mut() {
+                if *online && !seen.contains(id) {
+                    *online = false;
+                    events.push(LanCommands::event("offline", state, None));
+                }
+            }
+
+            if !events.is_empty() {
+                self.output.print_stream(&events, first, || LanCommands::print_watch_table(&known, &events))?;
+                first = false;
+            }
+
+            tokio::time::sleep(Duration::from_secs_f64(interval)).await;
+        }
+    }
+
+    fn event(event: &str, state: &LanDeviceState, change: Option<(String, Value, Value)>) -> LanStateEvent {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default();
+
+        let (field, old, new) = match change {
+            Some((field, old, new)) => (Some(field), Some(old), Some(new)),
+            None => (None, None, None),
+        };
+
+        LanStateEvent {
+            timestamp,
+            event: event.to_string(),
+            id: state.id.clone(),
+            label: state.label.clone(),
+            field,
+            old,
+            new,
+        }
+    }
+
+    /*
+        Redraw the table of every known device, highlighting the ones that just changed
+    */
+    fn print_watch_table(known: &BTreeMap<String, (LanDeviceState, bool)>, events: &[LanStateEvent]) {
+        let mut table = Table::new();
+        table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+        table.add_row(row![
+            b -> "ID",
+            b -> "Label",
+            b -> "Power",
+            b -> "Brightness",
+            b -> "Hue",
+            b -> "Saturation",
+            b -> "Kelvin",
+            b -> "Status",
+        ]);
+
+        for (id, (state, online)) in known {
+            state.serialize_row(&mut table);
+
+            let row = table.get_mut_row(table.len() - 1).unwrap();
+            row.add_cell(cell!(if *online { "online" } else { "offline" }));
+
+            if events.iter().any(|event| &event.id == id) {
+                let highlight = if *online { color::YELLOW } else { color::RED };
+
+                for cell in row.iter_mut() {
+                    cell.style(Attr::ForegroundColor(highlight));
+                }
+            }
+        }
+
+        /* Clear the screen and move the cursor home so the table updates in place */
+        print!("\x1B[2J\x1B[H");
+        table.printstd();
+    }
+}
@@ -0,0 +1,349 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use lifx_cli::{error::LifxError, types::ListLightResponse};
+use prettytable::{Table, Cell, Row, format};
+use ansi_rgb::Background;
+use rgb::RGB8;
+use hsl::HSL;
+
+/* A field of a light that `lights list` can show, sort by or filter on */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LightField {
+    Id,
+    Uuid,
+    Label,
+    Connected,
+    Power,
+    Brightness,
+    Color,
+    Hue,
+    Saturation,
+    Kelvin,
+    Effect,
+    Group,
+    Location,
+    Product,
+    Capabilities,
+    LastSeen,
+    SecondsSinceSeen,
+    Via,
+}
+
+/* Every field by the name used on the command line, in the order they're listed in help */
+const FIELDS: [(&str, LightField); 18] = [
+    ("id", LightField::Id),
+    ("uuid", LightField::Uuid),
+    ("label", LightField::Label),
+    ("connected", LightField::Connected),
+    ("power", LightField::Power),
+    ("brightness", LightField::Brightness),
+    ("color", LightField::Color),
+    ("hue", LightField::Hue),
+    ("saturation", LightField::Saturation),
+    ("kelvin", LightField::Kelvin),
+    ("effect", LightField::Effect),
+    ("group", LightField::Group),
+    ("location", LightField::Location),
+    ("product", LightField::Product),
+    ("capabilities", LightField::Capabilities),
+    ("last_seen", LightField::LastSeen),
+    ("seconds_since_seen", LightField::SecondsSinceSeen),
+    ("via", LightField::Via),
+];
+
+const DEFAULT_COLUMNS: [LightField; 6] = [
+    LightField::Id,
+    LightField::Label,
+    LightField::Connected,
+    LightField::Power,
+    LightField::Brightness,
+    LightField::Color,
+];
+
+pub fn field_names() -> String {
+    FIELDS.iter().map(|(name, _)| *name).collect::<Vec<&str>>().join(", ")
+}
+
+impl FromStr for LightField {
+    type Err = LifxError;
+
+    fn from_str(name: &str) -> Result<LightField, LifxError> {
+        FIELDS
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name.trim()))
+            .map(|(_, field)| *field)
+            .ok_or_else(|| LifxError::Validation(format!("Unknown field '{}', expected one of {}", name, field_names())))
+    }
+}
+
+/* The value of a field, typed so numbers sort and compare as numbers */
+#[derive(Clone, PartialEq, Debug)]
+pub enum FieldValue {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Text(text) => write!(f, "{}", text),
+            FieldValue::Number(number) => write!(f, "{}", number),
+            FieldValue::Bool(boolean) => write!(f, "{}", boolean),
+        }
+    }
+}
+
+impl FieldValue {
+    /* Text is compared ignoring case */
+    fn compare(&self, other: &FieldValue) -> Ordering {
+        match (self, other) {
+            (FieldValue::Number(a), FieldValue::Number(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            (FieldValue::Bool(a), FieldValue::Bool(b)) => a.cmp(b),
+            (a, b) => a.to_string().to_lowercase().cmp(&b.to_string().to_lowercase()),
+        }
+    }
+
+    /* Compare with a value given on the command line, read as the same type. None when it can't be */
+    fn compare_to(&self, value: &str) -> Option<Ordering> {
+        let value = match self {
+            FieldValue::Text(_) => FieldValue::Text(value.to_string()),
+            FieldValue::Number(_) => FieldValue::Number(value.parse().ok().filter(|value: &f64| !value.is_nan())?),
+            FieldValue::Bool(_) => FieldValue::Bool(value.to_lowercase().parse().ok()?),
+        };
+
+        Some(self.compare(&value))
+    }
+}
+
+impl LightField {
+    fn title(&self) -> &'static str {
+        match self {
+            LightField::Id => "ID",
+            LightField::Uuid => "UUID",
+            LightField::Label => "Label",
+            LightField::Connected => "Connected",
+            LightField::Power => "Power",
+            LightField::Brightness => "Brightness",
+            LightField::Color => "Color",
+            LightField::Hue => "Hue",
+            LightField::Saturation => "Saturation",
+            LightField::Kelvin => "Kelvin",
+            LightField::Effect => "Effect",
+            LightField::Group => "Group",
+            LightField::Location => "Location",
+            LightField::Product => "Product",
+            LightField::Capabilities => "Capabilities",
+            LightField::LastSeen => "Last Seen",
+            LightField::SecondsSinceSeen => "Seconds Since Seen",
+            LightField::Via => "Via",
+        }
+    }
+
+    pub fn value(&self, light: &ListLightResponse) -> FieldValue {
+        match self {
+            LightField::Id => FieldValue::Text(light.id.clone()),
+            LightField::Uuid => FieldValue::Text(light.uuid.clone()),
+            LightField::Label => FieldValue::Text(light.label.clone()),
+            LightField::Connected => FieldValue::Bool(light.connected),
+            LightField::Power => FieldValue::Text(light.power.clone()),
+            LightField::Brightness => FieldValue::Number(light.brightness),
+            /* In the color string format, so it can be pasted into set-state */
+            LightField::Color => FieldValue::Text(format!(
+                "hue:{} saturation:{} kelvin:{}",
+                light.color.hue, light.color.saturation, light.color.kelvin,
+            )),
+            LightField::Hue => FieldValue::Number(light.color.hue),
+            LightField::Saturation => FieldValue::Number(light.color.saturation),
+            LightField::Kelvin => FieldValue::Number(light.color.kelvin),
+            LightField::Effect => FieldValue::Text(light.effect.as_deref().map_or(String::new(), str::to_string)),
+            LightField::Group => FieldValue::Text(light.group.name.clone()),
+            LightField::Location => FieldValue::Text(light.location.name.clone()),
+            LightField::Product => FieldValue::Text(light.product.name.clone()),
+            LightField::Capabilities => {
+                let capabilities = &light.product.capabilities;
+
+                let names = [
+                    (capabilities.has_color, "color"),
+                    (capabilities.has_variable_color_temp, "variable_color_temp"),
+                    (capabilities.has_ir, "ir"),
+                    (capabilities.has_hev, "hev"),
+                    (capabilities.has_chain, "chain"),
+                    (capabilities.has_matrix, "matrix"),
+                    (capabilities.has_multizone, "multizone"),
+                ];
+
+                FieldValue::Text(names.iter().filter(|(has, _)| *has).map(|(_, name)| *name).collect::<Vec<&str>>().join(","))
+            },
+            LightField::LastSeen => FieldValue::Text(light.last_seen.clone()),
+            LightField::SecondsSinceSeen => FieldValue::Number(light.seconds_since_seen as f64),
+            LightField::Via => FieldValue::Text(light.transport.clone().unwrap_or_default()),
+        }
+    }
+
+    fn cell(&self, light: &ListLightResponse) -> Cell {
+        match self {
+            LightField::Brightness => Cell::new(&format!("{}%", light.brightness * 100.0)),
+            LightField::Color => {
+                let hsl = HSL {
+                    h: light.color.hue,
+                    s: light.color.saturation * 100.0,
+                    l: if light.color.saturation == 0.0 { 1.1 } else { 0.5 },
+                };
+
+                let (r, g, b) = hsl.to_rgb();
+
+                Cell::new(&"     ".bg(RGB8::new(r, g, b)).to_string())
+            },
+            field => Cell::new(&field.value(light).to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Operator {
+    Equals,
+    NotEquals,
+    Contains,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+/*
+    A condition every listed light must meet, e.g. power=on, product~Mini or brightness>=0.5.
+    ~ matches part of the value and text is compared ignoring case
+*/
+#[derive(Clone, PartialEq, Debug)]
+pub struct Filter {
+    field: LightField,
+    operator: Operator,
+    value: String,
+}
+
+impl FromStr for Filter {
+    type Err = LifxError;
+
+    fn from_str(filter: &str) -> Result<Filter, LifxError> {
+        let invalid = || LifxError::Validation(format!("Invalid filter '{}', expected a field, one of =, !=, ~, >, >=, < or <= and a value, e.g. power=on", filter));
+
+        let start = filter.find(|character| "=!~<>".contains(character)).ok_or_else(invalid)?;
+
+        let (operator, length) = match &filter[start..] {
+            rest if rest.starts_with("!=") => (Operator::NotEquals, 2),
+            rest if rest.starts_with(">=") => (Operator::GreaterOrEqual, 2),
+            rest if rest.starts_with("<=") => (Operator::LessOrEqual, 2),
+            rest if rest.starts_with('=') => (Operator::Equals, 1),
+            rest if rest.starts_with('~') => (Operator::Contains, 1),
+            rest if rest.starts_with('>') => (Operator::Greater, 1),
+            rest if rest.starts_with('<') => (Operator::Less, 1),
+            _ => return Err(invalid()),
+        };
+
+        let field: LightField = filter[..start].parse()?;
+        let value = filter[start + length..].trim().to_string();
+
+        /* A value that can't be read as the field's type would match every light with != and none otherwise */
+        if operator != Operator::Contains && field.value(&ListLightResponse::default()).compare_to(&value).is_none() {
+            return Err(LifxError::Validation(format!("Invalid filter '{}', '{}' can't be compared with {}", filter, value, filter[..start].trim())));
+        }
+
+        Ok(Filter { field, operator, value })
+    }
+}
+
+impl Filter {
+    pub fn matches(&self, light: &ListLightResponse) -> bool {
+        let actual = self.field.value(light);
+
+        match self.operator {
+            Operator::Contains => actual.to_string().to_lowercase().contains(&self.value.to_lowercase()),
+            Operator::Equals => actual.compare_to(&self.value) == Some(Ordering::Equal),
+            Operator::NotEquals => actual.compare_to(&self.value) != Some(Ordering::Equal),
+            Operator::Greater => actual.compare_to(&self.value) == Some(Ordering::Greater),
+            Operator::GreaterOrEqual => matches!(actual.compare_to(&self.value), Some(Ordering::Greater | Ordering::Equal)),
+            Operator::Less => actual.compare_to(&self.value) == Some(Ordering::Less),
+            Operator::LessOrEqual => matches!(actual.compare_to(&self.value), Some(Ordering::Less | Ordering::Equal)),
+        }
+    }
+}
+
+/* A field to sort by, descending when prefixed with - */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SortKey {
+    field: LightField,
+    descending: bool,
+}
+
+impl FromStr for SortKey {
+    type Err = LifxError;
+
+    fn from_str(key: &str) -> Result<SortKey, LifxError> {
+        match key.trim().strip_prefix('-') {
+            Some(field) => Ok(SortKey { field: field.parse()?, descending: true }),
+            None => Ok(SortKey { field: key.parse()?, descending: false }),
+        }
+    }
+}
+
+impl SortKey {
+    fn compare(&self, a: &ListLightResponse, b: &ListLightResponse) -> Ordering {
+        let ordering = self.field.value(a).compare(&self.field.value(b));
+
+        match self.descending {
+            true => ordering.reverse(),
+            false => ordering,
+        }
+    }
+}
+
+/*
+    Which lights `lights list` shows, in what order and with which columns
+*/
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct ListView {
+    pub columns: Option<Vec<LightField>>,
+    pub sort: Vec<SortKey>,
+    pub filters: Vec<Filter>,
+}
+
+impl ListView {
+    /* The lights that meet every filter, sorted by each key in turn */
+    pub fn apply(&self, lights: Vec<ListLightResponse>) -> Vec<ListLightResponse> {
+        let mut lights: Vec<ListLightResponse> = lights.into_iter().filter(|light| self.filters.iter().all(|filter| filter.matches(light))).collect();
+
+        lights.sort_by(|a, b| {
+            self.sort
+                .iter()
+                .map(|key| key.compare(a, b))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+
+        lights
+    }
+
+    pub fn table(&self, lights: &[ListLightResponse]) -> Table {
+        let columns = match &self.columns {
+            Some(columns) => columns.clone(),
+            None => {
+                /* Lights listed over more than one transport also say which one each was found by */
+                let via = lights.iter().any(|light| light.transport.is_some());
+
+                DEFAULT_COLUMNS.iter().copied().chain(via.then_some(LightField::Via)).collect()
+            },
+        };
+
+        let mut table = Table::new();
+        table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+
+        table.add_row(Row::new(columns.iter().map(|column| cell!(b -> column.title())).collect()));
+
+        for light in lights {
+            table.add_row(Row::new(columns.iter().map(|column| column.cell(light)).collect()));
+        }
+
+        table
+    }
+}
@@ -1,5 +1,5 @@
 pub mod commands;
-pub mod types;
 pub mod cli_printables;
-pub mod lan;
-pub mod lan_service;
\ No newline at end of file
+pub mod lan_commands;
+pub mod list_view;
+pub mod output;
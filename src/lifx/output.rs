@@ -0,0 +1,146 @@
+use std::{io, str::FromStr};
+
+use lifx_cli::error::LifxError;
+use serde::Serialize;
+use serde_json::Value;
+
+pub const FORMATS: [&str; 5] = ["table", "json", "yaml", "csv", "ndjson"];
+
+/*
+    How command output is printed. Every format but a table uses the field names of the API responses,
+    with nested fields flattened to columns like `group.name` in CSV
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Output {
+    Table,
+    Json,
+    Yaml,
+    Csv,
+    Ndjson,
+}
+
+impl FromStr for Output {
+    type Err = LifxError;
+
+    fn from_str(format: &str) -> Result<Output, LifxError> {
+        match format {
+            "table" => Ok(Output::Table),
+            "json" => Ok(Output::Json),
+            "yaml" => Ok(Output::Yaml),
+            "csv" => Ok(Output::Csv),
+            "ndjson" => Ok(Output::Ndjson),
+            _ => Err(LifxError::Validation(format!("Unknown output format '{}', expected one of {}", format, FORMATS.join(", ")))),
+        }
+    }
+}
+
+impl Output {
+    /*
+        Print a whole response as JSON or YAML, or one line per record (each light, scene or device) as CSV or NDJSON.
+        `table` draws it as a table
+    */
+    pub fn print<T, R>(&self, response: &T, records: &[R], table: impl FnOnce()) -> Result<(), LifxError>
+    where
+        T: Serialize,
+        R: Serialize,
+    {
+        match self {
+            Output::Table => table(),
+            Output::Json => println!("{}", serde_json::to_string_pretty(response)?),
+            Output::Yaml => print!("{}", yaml(response)?),
+            Output::Csv => csv(records, true)?,
+            Output::Ndjson => ndjson(records)?,
+        }
+
+        Ok(())
+    }
+
+    /*
+        Print records as they arrive. A JSON document can't be added to once printed, so JSON is printed a record
+        per line like NDJSON, YAML a document per record and CSV with a header before the first records only
+    */
+    pub fn print_stream<R: Serialize>(&self, records: &[R], first: bool, table: impl FnOnce()) -> Result<(), LifxError> {
+        match self {
+            Output::Table => table(),
+            Output::Json | Output::Ndjson => ndjson(records)?,
+            Output::Yaml => {
+                for record in records {
+                    print!("---\n{}", yaml(record)?);
+                }
+            },
+            Output::Csv => csv(records, first)?,
+        }
+
+        Ok(())
+    }
+}
+
+fn yaml<T: Serialize>(value: &T) -> Result<String, LifxError> {
+    serde_yaml::to_string(value).map_err(|error| LifxError::Parse(format!("YAML: {}", error)))
+}
+
+fn ndjson<R: Serialize>(records: &[R]) -> Result<(), LifxError> {
+    for record in records {
+        println!("{}", serde_json::to_string(record)?);
+    }
+
+    Ok(())
+}
+
+/*
+    One column per field of any record, in the order they're first seen. Records without a field leave it empty
+*/
+fn csv<R: Serialize>(records: &[R], header: bool) -> Result<(), LifxError> {
+    let mut rows: Vec<Vec<(String, String)>> = vec![];
+
+    for record in records {
+        let mut row = vec![];
+        flatten("", &serde_json::to_value(record)?, &mut row);
+        rows.push(row);
+    }
+
+    let mut columns: Vec<&str> = vec![];
+
+    for (column, _) in rows.iter().flatten() {
+        if !columns.contains(&column.as_str()) {
+            columns.push(column);
+        }
+    }
+
+    let mut writer = csv::Writer::from_writer(io::stdout());
+
+    if header {
+        writer.write_record(&columns).map_err(io::Error::from)?;
+    }
+
+    for row in &rows {
+        let cells = columns.iter().map(|column| row.iter().find(|(name, _)| name == column).map_or("", |(_, cell)| cell.as_str()));
+
+        writer.write_record(cells).map_err(io::Error::from)?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/*
+    Nested objects become `parent.child` columns. Arrays are kept whole as JSON
+*/
+fn flatten(prefix: &str, value: &Value, row: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(fields) => {
+            for (name, value) in fields {
+                let column = match prefix {
+                    "" => name.clone(),
+                    prefix => format!("{}.{}", prefix, name),
+                };
+
+                flatten(&column, value, row);
+            }
+        },
+        Value::Null => row.push((prefix.to_string(), String::new())),
+        Value::String(string) => row.push((prefix.to_string(), string.clone())),
+        value => row.push((prefix.to_string(), value.to_string())),
+    }
+}
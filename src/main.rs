@@ -1,20 +1,37 @@
-use std::{io::{stdin, Write, stdout}, net::{SocketAddr, Ipv4Addr, IpAddr}};
+use std::{io::{stdin, Write, stdout}, str::FromStr, time::Duration};
 
-use clap::{command, arg, Command, AppSettings};
+use clap::{command, arg, ArgMatches, Command, AppSettings};
+use lifx::{commands::LifxCommands, list_view::{field_names, Filter, LightField, ListView, SortKey}, output::{Output, FORMATS}};
+use lifx_cli::{cloud::{Client, ClientOptions}, controller::{Auto, LightController}, error::LifxError, lan::{self, scheduler::DEFAULT_RATE}};
 use log::debug;
 use system_config::Config;
-use crate::lifx::lan::{LifxPacket::GetService, LifxPacket::SetPower, StateServiceResponse, SetLightPowerPayload};
 
 #[macro_use] extern crate prettytable;
 mod lifx;
 
 const API_KEY_CONFIG_KEY: &str = "api_key";
+const API_URL_CONFIG_KEY: &str = "api_url";
+const PROXY_CONFIG_KEY: &str = "proxy";
+const TIMEOUT_CONFIG_KEY: &str = "timeout";
+const CONNECT_TIMEOUT_CONFIG_KEY: &str = "connect_timeout";
+
+/* Overrides the api_url config value */
+const API_URL_ENV: &str = "LIFX_API_URL";
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() {
     env_logger::init();
 
-    let mut config  = Config::new("lifx-cli-config").unwrap();
+    if let Err(error) = run().await {
+        eprintln!("Error: {}", error);
+        std::process::exit(error.exit_code());
+    }
+}
+
+async fn run() -> Result<(), LifxError> {
+    let mut config = Config::new("lifx-cli-config").map_err(|error| LifxError::Config(error.to_string()))?;
+
+    let fields_help = format!("Fields for --columns, --sort and --where: {}", field_names());
 
     let command = command!()
         .setting(AppSettings::SubcommandRequiredElseHelp)
@@ -34,6 +51,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .subcommand(
                     Command::new("list")
                         .about("List lights")
+                        .arg(
+                            arg!(--columns <fields> "Comma separated fields to show as table columns")
+                                .required(false)
+                                .use_value_delimiter(true)
+                                .value_parser(|field: &str| field.parse::<LightField>())
+                        )
+                        .arg(
+                            arg!(--sort <fields> "Comma separated fields to sort by. Prefix a field with - to sort it in descending order")
+                                .required(false)
+                                .use_value_delimiter(true)
+                                .allow_hyphen_values(true)
+                                .value_parser(|key: &str| key.parse::<SortKey>())
+                        )
+                        .arg(
+                            arg!(--"where" <filter> "Only list lights where a field is =, !=, >, >=, < or <= a value, or contains it with ~, e.g. power=on or product~Mini. Repeat to require more")
+                                .required(false)
+                                .multiple_occurrences(true)
+                                .value_parser(|filter: &str| filter.parse::<Filter>())
+                        )
+                        .after_help(fields_help.as_str())
                 )
                 .subcommand(
                     Command::new("toggle")
@@ -42,6 +79,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             arg!(-d --duration "The time in seconds to spend perfoming the power toggle")
                                 .default_value("0.0")
                         )
+                        .arg(
+                            arg!(--retry [retries] "Set lights that timed out to the toggled power state, up to this many times")
+                                .value_parser(clap::value_parser!(u32))
+                        )
                 )
                 .subcommand(
                     Command::new("set-state")
@@ -65,18 +106,240 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .arg(
                             arg!(-f --fast "Execute the query fast, without initial state checks and wait for no results.")
                         )
+                        .arg(
+                            arg!(--retry [retries] "Send the state again to lights that timed out, up to this many times")
+                                .value_parser(clap::value_parser!(u32))
+                        )
+                )
+                .subcommand(
+                    Command::new("adjust")
+                        .about("Change the state of light/s relative to their current state")
+                        .arg(
+                            arg!(-d --duration [duration] "The time in seconds to make the state change over")
+                                .default_value("0.0")
+                        )
+                        .arg(
+                            arg!(-p --power [power] "Power state (on/off)")
+                        )
+                        .arg(
+                            arg!(-i --infrared [infrared] "Change to the maximum infrared brightness, between -1.0 and 1.0")
+                                .allow_hyphen_values(true)
+                        )
+                        .arg(
+                            arg!(-u --hue [hue] "Change to the hue in degrees, between -360 and 360. The result wraps around")
+                                .allow_hyphen_values(true)
+                        )
+                        .arg(
+                            arg!(-t --saturation [saturation] "Change to the saturation, between -1.0 and 1.0")
+                                .allow_hyphen_values(true)
+                        )
+                        .arg(
+                            arg!(-b --brightness [brightness] "Change to the brightness, between -1.0 and 1.0")
+                                .allow_hyphen_values(true)
+                        )
+                        .arg(
+                            arg!(-k --kelvin [kelvin] "Change to the color temperature in kelvin")
+                                .allow_hyphen_values(true)
+                        )
+                )
+                .subcommand(
+                    Command::new("breathe")
+                        .about("Slowly fade light/s between two colors")
+                        .arg(
+                            arg!(-c --color <color> "The color to use for the breathe effect")
+                        )
+                        .arg(
+                            arg!(--"from-color" [from_color] "The color to start the effect from. Defaults to the current color of the light")
+                        )
+                        .arg(
+                            arg!(--period [period] "The time in seconds for one cycle of the effect")
+                        )
+                        .arg(
+                            arg!(--cycles [cycles] "The number of times to repeat the effect")
+                        )
+                        .arg(
+                            arg!(--persist "Keep the last color of the effect instead of returning to the original color")
+                        )
+                        .arg(
+                            arg!(--"power-on" [power_on] "Turn the light on if it is off (true/false). Defaults to true")
+                        )
+                        .arg(
+                            arg!(--peak [peak] "Where in a period the target color is at its maximum, between 0.0 and 1.0")
+                        )
+                )
+                .subcommand(
+                    Command::new("pulse")
+                        .about("Quickly flash light/s between two colors")
+                        .arg(
+                            arg!(-c --color <color> "The color to use for the pulse effect")
+                        )
+                        .arg(
+                            arg!(--"from-color" [from_color] "The color to start the effect from. Defaults to the current color of the light")
+                        )
+                        .arg(
+                            arg!(--period [period] "The time in seconds for one cycle of the effect")
+                        )
+                        .arg(
+                            arg!(--cycles [cycles] "The number of times to repeat the effect")
+                        )
+                        .arg(
+                            arg!(--persist "Keep the last color of the effect instead of returning to the original color")
+                        )
+                        .arg(
+                            arg!(--"power-on" [power_on] "Turn the light on if it is off (true/false). Defaults to true")
+                        )
+                )
+                .subcommand(
+                    Command::new("effect")
+                        .setting(AppSettings::SubcommandRequiredElseHelp)
+                        .about("Start or stop firmware effects on strips, tiles and other matrix lights. Always sent through the cloud API, so unavailable with --transport lan")
+                        .subcommand(
+                            Command::new("move")
+                                .about("Move the current colors along a multizone strip")
+                                .arg(arg!(--direction [direction] "The direction to move in").possible_values(["forward", "backward"]))
+                                .arg(arg!(--period [period] "The time in seconds for one cycle of the effect"))
+                                .arg(arg!(--cycles [cycles] "The number of times to move the pattern"))
+                                .arg(arg!(--"power-on" [power_on] "Turn the light on if it is off (true/false). Defaults to true"))
+                                .arg(arg!(-f --fast "Execute the query fast, without initial state checks and wait for no results."))
+                        )
+                        .subcommand(
+                            Command::new("morph")
+                                .about("Blend a palette of colors across a matrix light")
+                                .arg(arg!(--period [period] "The time in seconds for one cycle of the effect"))
+                                .arg(arg!(--duration [duration] "How long in seconds to run the effect. Runs until stopped if omitted"))
+                                .arg(arg!(--palette <color> "A color for the palette. Repeat to add more colors").required(false).multiple_occurrences(true))
+                                .arg(arg!(--"power-on" [power_on] "Turn the light on if it is off (true/false). Defaults to true"))
+                                .arg(arg!(-f --fast "Execute the query fast, without initial state checks and wait for no results."))
+                        )
+                        .subcommand(
+                            Command::new("flame")
+                                .about("Flickering flame effect on a matrix light")
+                                .arg(arg!(--period [period] "The time in seconds for one cycle of the effect"))
+                                .arg(arg!(--duration [duration] "How long in seconds to run the effect. Runs until stopped if omitted"))
+                                .arg(arg!(--"power-on" [power_on] "Turn the light on if it is off (true/false). Defaults to true"))
+                                .arg(arg!(-f --fast "Execute the query fast, without initial state checks and wait for no results."))
+                        )
+                        .subcommand(
+                            Command::new("clouds")
+                                .about("Drifting clouds effect on a matrix light")
+                                .arg(arg!(--duration [duration] "How long in seconds to run the effect. Runs until stopped if omitted"))
+                                .arg(arg!(--palette <color> "A color for the palette. Repeat to add more colors").required(false).multiple_occurrences(true))
+                                .arg(arg!(--"power-on" [power_on] "Turn the light on if it is off (true/false). Defaults to true"))
+                                .arg(arg!(-f --fast "Execute the query fast, without initial state checks and wait for no results."))
+                        )
+                        .subcommand(
+                            Command::new("sunrise")
+                                .about("Simulate a sunrise on a matrix light")
+                                .arg(arg!(--duration [duration] "How long in seconds the sunrise takes"))
+                                .arg(arg!(--"power-on" [power_on] "Turn the light on if it is off (true/false). Defaults to true"))
+                                .arg(arg!(-f --fast "Execute the query fast, without initial state checks and wait for no results."))
+                        )
+                        .subcommand(
+                            Command::new("sunset")
+                                .about("Simulate a sunset on a matrix light")
+                                .arg(arg!(--duration [duration] "How long in seconds the sunset takes"))
+                                .arg(arg!(--"power-on" [power_on] "Turn the light on if it is off (true/false). Defaults to true"))
+                                .arg(arg!(-f --fast "Execute the query fast, without initial state checks and wait for no results."))
+                        )
+                        .subcommand(
+                            Command::new("off")
+                                .about("Stop any running effect")
+                                .arg(arg!(--"power-off" "Also turn the light/s off"))
+                        )
+                )
+                .subcommand(
+                    Command::new("cycle")
+                        .about("Step light/s to the next state in a list")
+                        .arg(
+                            arg!(--states <file> "JSON or YAML file with a list of 'states' and optional shared 'defaults', or just a list of states")
+                        )
+                        .arg(
+                            arg!(--direction [direction] "The direction to step through the states in")
+                                .possible_values(["forward", "backward"])
+                        )
+                )
+                .subcommand(
+                    Command::new("clean")
+                        .about("Start or stop a HEV clean cycle on LIFX Clean bulbs")
+                        .arg(
+                            arg!(-d --duration [duration] "The length of the clean cycle in seconds. Uses the bulb's default when omitted")
+                        )
+                        .arg(
+                            arg!(--stop "Stop a running clean cycle")
+                                .conflicts_with("duration")
+                        )
+                )
+                .subcommand(
+                    Command::new("set-states")
+                        .about("Set multiple states across multiple selectors in one request")
+                        .arg(
+                            arg!(<file> "JSON or YAML file with a list of 'states' (each with a 'selector') and optional shared 'defaults'. See https://api.developer.lifx.com/docs/set-states")
+                        )
+                        .arg(
+                            arg!(-f --fast "Execute the query fast, without initial state checks and wait for no results.")
+                        )
                 )
                 .arg(
-                    arg!(-s --selector <selector> "Selector to filter lights. Omit to affect all lights. See https://api.developer.lifx.com/docs/selectors for selector documentation")
+                    arg!(-s --selector [selector] "Selector to filter lights. Omit to affect all lights. See https://api.developer.lifx.com/docs/selectors for selector documentation")
                         .default_value("all")
                 )
         )
+        .subcommand(
+            Command::new("color")
+                .about("Validate a color string and preview the color it describes")
+                .arg(
+                    arg!(<string> "Color string. See https://api.developer.lifx.com/v1/docs/colors for color documentation")
+                )
+                .arg(
+                    arg!(--offline "Parse the color locally instead of asking the API")
+                )
+        )
+        .subcommand(
+            Command::new("scenes")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Scenes module")
+                .subcommand(
+                    Command::new("list")
+                        .about("List scenes")
+                )
+                .subcommand(
+                    Command::new("activate")
+                        .about("Activate a scene")
+                        .arg(
+                            arg!(<scene> "The UUID or name of the scene")
+                        )
+                        .arg(
+                            arg!(-d --duration [duration] "The time in seconds to spend transitioning to the scene")
+                        )
+                        .arg(
+                            arg!(--ignore <fields> "Comma separated state fields to leave unchanged (power, infrared, duration, intensity, color, hue, saturation, brightness, kelvin)")
+                                .required(false)
+                                .use_value_delimiter(true)
+                        )
+                        .arg(
+                            arg!(--overrides <override> "A field=value pair applied to every light in the scene, e.g. brightness=0.5. Repeat for more fields")
+                                .required(false)
+                                .multiple_occurrences(true)
+                        )
+                        .arg(
+                            arg!(-f --fast "Execute the query fast, without initial state checks and wait for no results.")
+                        )
+                )
+        )
         .subcommand(
             Command::new("lan")
                 .about("UDP LAN commands")
                 .subcommand(
                     Command::new("discover")
-                        .about("Discover devices on network")
+                        .about("Discover devices on network matching the selector")
+                )
+                .subcommand(
+                    Command::new("watch")
+                        .about("Poll devices and print changes to their power, color and label")
+                        .arg(
+                            arg!(--interval [interval] "The time in seconds between polls")
+                                .default_value("5.0")
+                        )
                 )
                 .subcommand(
                     Command::new("power")
@@ -85,19 +348,65 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         arg!(<state> "on/off")
                     )
                 )
+                .subcommand(
+                    Command::new("set-state")
+                        .about("Set the power and color of devices")
+                        .arg(
+                            arg!(-d --duration [duration] "The time in seconds to make the state change over")
+                                .default_value("0.0")
+                        )
+                        .arg(
+                            arg!(-p --power [power] "Power state (on/off)")
+                        )
+                        .arg(
+                            arg!(-c --color [color] "Color of the light. See https://api.developer.lifx.com/v1/docs/colors for color documentation")
+                        )
+                        .arg(
+                            arg!(-b --brightness [brightness] "Brightness between 0.0 and 1.0")
+                        )
+                )
+                .arg(
+                    arg!(-i --ip [IP_Address] "The IP Address of the device to target for non-broadcast commands and queries. Overrides the selector")
+                )
                 .arg(
-                    arg!(-i --ip [IP_Address] "The IP Address of the device to target for non-broadcast commands and queries")
+                    arg!(-s --selector [selector] "Selector to filter discovered devices. Omit to affect all devices. Supports all, id, label, group, group_id, location and location_id")
+                        .default_value("all")
                 )
         )
         .arg(
-            arg!(-r --raw "Display raw json response data instead of tables")
+            arg!(-o --output [format] "How to print results. CSV and NDJSON print a line per light, scene or device")
+                .value_parser(FORMATS)
+                .default_value("table")
+                .global(true)
+        )
+        .arg(
+            arg!(-r --raw "Shorthand for --output json")
                 .takes_value(false)
+        )
+        .arg(
+            arg!(-v --verbose "Print each API response status, the remaining rate limit quota and any retries to stderr")
+                .global(true)
+        )
+        .arg(
+            arg!(--"allow-partial" "Exit successfully even when some lights didn't reach the requested state")
+                .global(true)
+        )
+        .arg(
+            arg!(--transport [transport] "How to reach lights: the cloud API, the LAN, or the LAN with the cloud API as a fallback")
+                .value_parser(["cloud", "lan", "auto"])
+                .default_value("cloud")
+                .global(true)
+        )
+        .arg(
+            arg!(--rate [rate] "Maximum number of messages per second sent to each device over the LAN")
+                .default_value("20")
+                .global(true)
         );
 
     let matches = &command.get_matches();
 
     if let Some(matches) = matches.subcommand_matches("auth") {
-        if let Some(_) = matches.subcommand_matches("clear") {
+        if matches.subcommand_matches("clear").is_some() {
             config.clear();
             println!("API key cleared");
         } else {
@@ -105,92 +414,302 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    debug!("Getting API Key");
-
-    let api_key = match config.get(API_KEY_CONFIG_KEY) {
-        Some(key_input) => {
-            debug!("Found key {}", key_input);
-            key_input
-        },
-        None => {
-            print!("Enter your LIFX API Key: ");
-            stdout().flush().unwrap();
-
-            let mut input = String::new();
+    let output: Output = match matches.contains_id("raw") {
+        true => Output::Json,
+        false => matches.get_one::<String>("output").map(String::as_str).unwrap_or("table").parse()?,
+    };
+    let verbose = matches.is_present("verbose");
+    let allow_partial = matches.is_present("allow-partial");
+    let transport = matches.get_one::<String>("transport").map(String::as_str).unwrap_or("cloud");
+    let rate = parse::<f64>(matches, "rate", "a number")?.unwrap_or(DEFAULT_RATE);
 
-            stdin().read_line(&mut input)?;
+    let mut client_options = ClientOptions { proxy: config.get(PROXY_CONFIG_KEY), verbose, ..Default::default() };
 
-            let trimmed = input.trim_end();
+    if let Some(api_url) = std::env::var(API_URL_ENV).ok().filter(|url| !url.is_empty()).or_else(|| config.get(API_URL_CONFIG_KEY)) {
+        client_options.base_url = api_url;
+    }
 
-            config.insert(API_KEY_CONFIG_KEY, &trimmed.clone());
-            config.write()?;
-            String::from(trimmed)
-        },
-    };
+    for (key, option) in [(TIMEOUT_CONFIG_KEY, &mut client_options.timeout), (CONNECT_TIMEOUT_CONFIG_KEY, &mut client_options.connect_timeout)] {
+        if let Some(value) = config.get(key) {
+            *option = value
+                .parse::<f64>()
+                .ok()
+                .filter(|seconds| *seconds > 0.0)
+                .map(Duration::from_secs_f64)
+                .ok_or_else(|| LifxError::Config(format!("'{}' must be a number of seconds greater than 0", key)))?;
+        }
+    }
 
-    let display_raw: bool = matches.contains_id("raw");
+    debug!("{:?}", client_options);
 
     if let Some(matches) = matches.subcommand_matches("lights") {
         debug!("lights module");
 
-        let lifx_commands: lifx::commands::LifxCommands = lifx::commands::LifxCommands::new(&api_key, &display_raw);
+        let lifx_commands = lifx_commands(transport, rate, &mut config, &client_options, output, allow_partial)?;
 
         let selector = matches.get_one::<String>("selector").unwrap();
 
         debug!("selector: {}", selector);
 
-        if let Some(_) = matches.subcommand_matches("list") {
+        if let Some(matches) = matches.subcommand_matches("list") {
             debug!("list command");
-            lifx_commands.list_lights(selector).await?;
+            let view = ListView {
+                columns: matches.get_many::<LightField>("columns").map(|columns| columns.copied().collect()),
+                sort: matches.get_many::<SortKey>("sort").map(|keys| keys.copied().collect()).unwrap_or_default(),
+                filters: matches.get_many::<Filter>("where").map(|filters| filters.cloned().collect()).unwrap_or_default(),
+            };
+
+            lifx_commands.list_lights(selector, &view).await?;
         }
 
         if let Some(matches) = matches.subcommand_matches("toggle") {
             debug!("toggle command");
-            let duration = matches.value_of_t::<f64>("duration").ok();
-            lifx_commands.toggle_lights(selector, duration).await?;
+            let duration = parse::<f64>(matches, "duration", "a number")?;
+            let retries = matches.get_one::<u32>("retry").copied().unwrap_or(0);
+            lifx_commands.toggle_lights(selector, duration, retries).await?;
         }
 
         if let Some(matches) = matches.subcommand_matches("set-state") {
             debug!("set-state command");
-            let duration = matches.value_of_t::<f64>("duration").ok();
-            let brightness = matches.value_of_t::<f64>("brightness").ok();
-            let infrared = matches.value_of_t::<f64>("infrared").ok();
-            let fast = matches.value_of_t::<bool>("fast").ok();
+            let duration = parse::<f64>(matches, "duration", "a number")?;
+            let brightness = parse::<f64>(matches, "brightness", "a number")?;
+            let infrared = parse::<f64>(matches, "infrared", "a number")?;
+            let fast = matches.is_present("fast").then_some(true);
             let power = matches.get_one::<String>("power");
             let color = matches.get_one::<String>("color");
 
-            lifx_commands.set_state(selector, power, color, brightness, duration, infrared, fast).await?;
+            let retries = matches.get_one::<u32>("retry").copied().unwrap_or(0);
+
+            lifx_commands.set_state(selector, power, color, brightness, duration, infrared, fast, retries).await?;
+        }
+
+        if let Some(matches) = matches.subcommand_matches("adjust") {
+            debug!("adjust command");
+            let duration = parse::<f64>(matches, "duration", "a number")?;
+            let power = matches.get_one::<String>("power");
+            let infrared = parse::<f64>(matches, "infrared", "a number")?;
+            let hue = parse::<f64>(matches, "hue", "a number")?;
+            let saturation = parse::<f64>(matches, "saturation", "a number")?;
+            let brightness = parse::<f64>(matches, "brightness", "a number")?;
+            let kelvin = parse::<i64>(matches, "kelvin", "a whole number")?;
+
+            lifx_commands.adjust(selector, power, duration, infrared, hue, saturation, brightness, kelvin).await?;
+        }
+
+        if let Some(matches) = matches.subcommand_matches("breathe") {
+            debug!("breathe command");
+            let color = matches.get_one::<String>("color").unwrap();
+            let from_color = matches.get_one::<String>("from-color");
+            let period = parse::<f64>(matches, "period", "a number")?;
+            let cycles = parse::<f64>(matches, "cycles", "a number")?;
+            let persist = matches.is_present("persist");
+            let power_on = parse::<bool>(matches, "power-on", "true or false")?;
+            let peak = parse::<f64>(matches, "peak", "a number")?;
+
+            lifx_commands.breathe(selector, color, from_color, period, cycles, persist, power_on, peak).await?;
+        }
+
+        if let Some(matches) = matches.subcommand_matches("pulse") {
+            debug!("pulse command");
+            let color = matches.get_one::<String>("color").unwrap();
+            let from_color = matches.get_one::<String>("from-color");
+            let period = parse::<f64>(matches, "period", "a number")?;
+            let cycles = parse::<f64>(matches, "cycles", "a number")?;
+            let persist = matches.is_present("persist");
+            let power_on = parse::<bool>(matches, "power-on", "true or false")?;
+
+            lifx_commands.pulse(selector, color, from_color, period, cycles, persist, power_on).await?;
+        }
+
+        if let Some(matches) = matches.subcommand_matches("effect") {
+            debug!("effect command");
+
+            match matches.subcommand() {
+                Some(("off", matches)) => {
+                    lifx_commands.effects_off(selector, matches.is_present("power-off")).await?;
+                },
+                Some((effect, matches)) => {
+                    /* Not every effect accepts every parameter */
+                    let value = |id: &str| matches.try_get_one::<String>(id).ok().flatten();
+
+                    let direction = value("direction");
+                    let period = parse::<f64>(matches, "period", "a number")?;
+                    let cycles = parse::<f64>(matches, "cycles", "a number")?;
+                    let duration = parse::<f64>(matches, "duration", "a number")?;
+                    let palette = matches.try_get_many::<String>("palette").ok().flatten().map(|colors| colors.collect()).unwrap_or_default();
+                    let power_on = parse::<bool>(matches, "power-on", "true or false")?;
+                    let fast = matches.is_present("fast");
+
+                    lifx_commands.effect(selector, effect, direction, period, cycles, duration, palette, power_on, fast).await?;
+                },
+                None => {}
+            }
+        }
+
+        if let Some(matches) = matches.subcommand_matches("cycle") {
+            debug!("cycle command");
+            let states = matches.get_one::<String>("states").unwrap();
+            let direction = matches.get_one::<String>("direction");
+
+            lifx_commands.cycle(selector, states, direction).await?;
+        }
+
+        if let Some(matches) = matches.subcommand_matches("clean") {
+            debug!("clean command");
+            let duration = parse::<u64>(matches, "duration", "a whole number")?;
+            let stop = matches.is_present("stop");
+
+            lifx_commands.clean(selector, duration, stop).await?;
+        }
+
+        if let Some(matches) = matches.subcommand_matches("set-states") {
+            debug!("set-states command");
+            let file = matches.get_one::<String>("file").unwrap();
+            let fast = matches.is_present("fast");
+
+            lifx_commands.set_states(file, fast).await?;
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("color") {
+        debug!("color module");
+
+        let lifx_commands = lifx_commands(transport, rate, &mut config, &client_options, output, allow_partial)?;
+
+        let color = matches.get_one::<String>("string").unwrap();
+
+        let offline = matches.is_present("offline");
+
+        lifx_commands.color(color, offline).await?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("scenes") {
+        debug!("scenes module");
+
+        let lifx_commands = lifx_commands(transport, rate, &mut config, &client_options, output, allow_partial)?;
+
+        if matches.subcommand_matches("list").is_some() {
+            debug!("list command");
+            lifx_commands.list_scenes().await?;
+        }
+
+        if let Some(matches) = matches.subcommand_matches("activate") {
+            debug!("activate command");
+            let scene = matches.get_one::<String>("scene").unwrap();
+            let duration = parse::<f64>(matches, "duration", "a number")?;
+            let ignore = matches.get_many::<String>("ignore").map(|fields| fields.collect()).unwrap_or_default();
+            let overrides = matches.get_many::<String>("overrides").map(|overrides| overrides.collect()).unwrap_or_default();
+            let fast = matches.is_present("fast");
+
+            lifx_commands.activate_scene(scene, duration, ignore, overrides, fast).await?;
         }
     }
 
     if let Some(matches) = matches.subcommand_matches("lan") {
-        let lan_service = lifx::lan_service::LanService::new();
+        debug!("lan module");
 
+        let lan_commands = lifx::lan_commands::LanCommands::new(output, rate, allow_partial)?;
+
+        let selector = matches.get_one::<String>("selector").unwrap();
         let target_address = matches.get_one::<String>("ip");
 
-        if let Some(_) = matches.subcommand_matches("discover") {
-            let (data, ip_addr) = lan_service.broadcast_query(GetService, None).expect("Not to fail");
+        debug!("selector: {}", selector);
 
-            let result = bincode::deserialize::<StateServiceResponse>(&data).unwrap();
-            
-            println!("Status: {:?}", result);
-            println!("IP Address: {:?}", ip_addr);
+        if matches.subcommand_matches("discover").is_some() {
+            debug!("discover command");
+            lan_commands.discover(selector, target_address).await?;
         }
 
-        if let Some(matches) = matches.subcommand_matches("power") {
-            let power_state = matches.get_one::<String>("state").expect("Power state (on/off) is required");
+        if let Some(matches) = matches.subcommand_matches("watch") {
+            debug!("watch command");
+            let interval = parse::<f64>(matches, "interval", "a number")?.unwrap_or_default();
+
+            if !(interval.is_finite() && interval > 0.0) {
+                return Err(LifxError::Validation(format!("'interval' must be a number of seconds greater than 0, got {}", interval)));
+            }
+
+            lan_commands.watch(selector, target_address, interval).await?;
+        }
 
-            let power_payload = SetLightPowerPayload::new(power_state == "on", 0);
+        if let Some(matches) = matches.subcommand_matches("power") {
+            debug!("power command");
+            let power_state = matches.get_one::<String>("state").ok_or_else(|| LifxError::Validation(String::from("Power state (on/off) is required")))?;
 
-            let ipv4: Ipv4Addr = target_address.expect("Target address is required for this command")
-                .parse()
-                .expect("Unable to parse socket address");
+            lan_commands.set_power(selector, target_address, power_state).await?;
+        }
 
-            let addr = SocketAddr::new(IpAddr::V4(ipv4), 56700);
+        if let Some(matches) = matches.subcommand_matches("set-state") {
+            debug!("set-state command");
+            let duration = parse::<f64>(matches, "duration", "a number")?.unwrap_or(0.0);
+            let power = matches.get_one::<String>("power");
+            let color = matches.get_one::<String>("color");
+            let brightness = parse::<f64>(matches, "brightness", "a number")?;
 
-            lan_service.send_command(addr, SetPower, Box::new(power_payload));
+            lan_commands.set_state(selector, target_address, power, color, brightness, duration).await?;
         }
     }
 
     Ok(())
 }
+
+/*
+    The value of an optional argument parsed strictly, so a typo is an error rather than the argument being left out.
+    Arguments that the subcommand doesn't define are treated as missing
+*/
+fn parse<T: FromStr>(matches: &ArgMatches, id: &str, expected: &str) -> Result<Option<T>, LifxError> {
+    match matches.try_get_one::<String>(id).ok().flatten() {
+        Some(value) => value
+            .parse::<T>()
+            .map(Some)
+            .map_err(|_| LifxError::Validation(format!("'{}' must be {}, got '{}'", id, expected, value))),
+        None => Ok(None),
+    }
+}
+
+/*
+    The API key from the config file, asking for one and saving it the first time
+*/
+fn read_api_key(config: &mut Config) -> Result<String, LifxError> {
+    debug!("Getting API Key");
+
+    let api_key = match config.get(API_KEY_CONFIG_KEY) {
+        Some(key_input) => {
+            debug!("Found key {}", key_input);
+            key_input
+        },
+        None => {
+            print!("Enter your LIFX API Key: ");
+            stdout().flush()?;
+
+            let mut input = String::new();
+
+            stdin().read_line(&mut input)?;
+
+            let trimmed = input.trim_end();
+
+            config.insert(API_KEY_CONFIG_KEY, trimmed);
+            config.write().map_err(|error| LifxError::Config(error.to_string()))?;
+            String::from(trimmed)
+        },
+    };
+
+    Ok(api_key)
+}
+
+/*
+    Light commands run over the chosen transport. The cloud API key is only needed when the cloud API may be used
+*/
+fn lifx_commands(transport: &str, rate: f64, config: &mut Config, options: &ClientOptions, output: Output, allow_partial: bool) -> Result<LifxCommands, LifxError> {
+    if transport == "lan" {
+        return Ok(LifxCommands::new(Box::new(lan::Client::new(rate)?), None, output, allow_partial, options.verbose));
+    }
+
+    let client = Client::new(&read_api_key(config)?, options)?;
+
+    let controller: Box<dyn LightController> = match transport {
+        "auto" => Box::new(Auto::new(lan::Client::new(rate)?, client.clone())),
+        _ => Box::new(client.clone()),
+    };
+
+    Ok(LifxCommands::new(controller, Some(client), output, allow_partial, options.verbose))
+}
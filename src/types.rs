@@ -0,0 +1,334 @@
+use std::net::SocketAddr;
+
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use serde_json::Value;
+use crate::error::LifxError;
+use optional_field::{Field, serde_optional_fields};
+
+// region: ListLightResponse
+
+#[serde_optional_fields]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListLightResponse {
+    pub id: String,
+    pub uuid: String,
+    pub label: String,
+    pub connected: bool,
+    pub power: String,
+    pub color: Color,
+    pub brightness: f64,
+    pub effect: Field<String>,
+    pub group: Group,
+    pub location: Location,
+    pub product: Product,
+    #[serde(rename = "last_seen")]
+    pub last_seen: String,
+    #[serde(rename = "seconds_since_seen")]
+    pub seconds_since_seen: i32,
+    /* How the light was reached, when more than one transport was tried */
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transport: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Color {
+    pub hue: f64,
+    pub saturation: f64,
+    pub kelvin: f64,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Group {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Product {
+    pub name: String,
+    pub identifier: String,
+    pub company: String,
+    #[serde(rename = "vendor_id")]
+    pub vendor_id: i64,
+    #[serde(rename = "product_id")]
+    pub product_id: i64,
+    pub capabilities: Capabilities,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    #[serde(rename = "has_color")]
+    pub has_color: bool,
+    #[serde(rename = "has_variable_color_temp")]
+    pub has_variable_color_temp: bool,
+    #[serde(rename = "has_ir")]
+    pub has_ir: bool,
+    #[serde(rename = "has_hev")]
+    pub has_hev: bool,
+    #[serde(rename = "has_chain")]
+    pub has_chain: bool,
+    #[serde(rename = "has_matrix")]
+    pub has_matrix: bool,
+    #[serde(rename = "has_multizone")]
+    pub has_multizone: bool,
+    #[serde(rename = "min_kelvin")]
+    pub min_kelvin: f64,
+    #[serde(rename = "max_kelvin")]
+    pub max_kelvin: f64,
+}
+
+// endregion: ListLightResponse
+
+// region: ToggledLightsResponse
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToggledLightsResponse {
+    pub results: Vec<Result>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Result {
+    pub id: Option<String>,
+    pub label: Option<String>,
+    pub status: Option<String>,
+    pub power: Option<String>,
+    /* How the light was reached, when more than one transport was tried */
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transport: Option<String>,
+}
+
+impl Result {
+    /* Whether the light reached the requested state. A superseded LAN write was replaced by a newer one that did */
+    pub fn succeeded(&self) -> bool {
+        matches!(self.status.as_deref(), Some("ok") | Some("superseded"))
+    }
+}
+
+// endregion: ToggledLightsResponse
+
+// region: SetStateResponse
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetStateResponse {
+    pub results: Vec<Result>,
+}
+
+// endregion: SetStateResponse
+
+// region: SetStatesResponse
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetStatesResponse {
+    pub results: Vec<OperationResult>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationResult {
+    pub operation: Value,
+    pub results: Vec<Result>,
+}
+
+// endregion: SetStatesResponse
+
+// region: LightResults
+
+/* Responses that report a status for every light they affected */
+pub trait LightResults {
+    fn light_results(&self) -> Vec<&Result>;
+
+    /*
+        Fails when any light didn't reach the requested state
+    */
+    fn check(&self) -> std::result::Result<(), LifxError> {
+        let results = self.light_results();
+        let failed = results.iter().filter(|result| !result.succeeded()).count();
+
+        match failed {
+            0 => Ok(()),
+            _ => Err(LifxError::PartialFailure { failed, total: results.len() }),
+        }
+    }
+}
+
+impl LightResults for ToggledLightsResponse {
+    fn light_results(&self) -> Vec<&Result> {
+        self.results.iter().collect()
+    }
+}
+
+impl LightResults for SetStateResponse {
+    fn light_results(&self) -> Vec<&Result> {
+        self.results.iter().collect()
+    }
+}
+
+impl LightResults for SetStatesResponse {
+    fn light_results(&self) -> Vec<&Result> {
+        self.results.iter().flat_map(|operation| &operation.results).collect()
+    }
+}
+
+// endregion: LightResults
+
+// region: SceneResponse
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SceneResponse {
+    pub uuid: String,
+    pub name: String,
+    pub account: Account,
+    pub states: Vec<SceneState>,
+    #[serde(rename = "created_at")]
+    pub created_at: i64,
+    #[serde(rename = "updated_at")]
+    pub updated_at: i64,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Account {
+    pub uuid: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SceneState {
+    pub selector: String,
+    pub power: Option<String>,
+    pub brightness: Option<f64>,
+    pub color: Option<Color>,
+}
+
+// endregion: SceneResponse
+
+// region: ValidateColorResponse
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidateColorResponse {
+    pub hue: Option<f64>,
+    pub saturation: Option<f64>,
+    pub brightness: Option<f64>,
+    pub kelvin: Option<f64>,
+}
+
+// endregion: ValidateColorResponse
+
+// region: ErrorResponse
+
+/* Body of every error status, e.g. an invalid color or an unknown selector */
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorResponse {
+    pub error: String,
+    #[serde(default)]
+    pub errors: Vec<Error>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Error {
+    pub field: String,
+    pub message: Vec<String>,
+}
+
+// endregion: ErrorResponse
+
+// region: RateLimit
+
+/* Read from the X-RateLimit-* headers sent with every response */
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimit {
+    pub limit: u64,
+    pub remaining: u64,
+    /* Unix time the quota resets at */
+    pub reset: u64,
+}
+
+// endregion: RateLimit
+
+// region: LanDevice
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LanDevice {
+    pub id: String,
+    pub label: String,
+    pub address: Option<SocketAddr>,
+    pub group: Group,
+    pub location: Location,
+    #[serde(skip)]
+    pub serial: [u8; 6],
+}
+
+// endregion: LanDevice
+
+
+// region: LanDeviceState
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LanDeviceState {
+    pub id: String,
+    pub label: String,
+    pub power: String,
+    pub hue: f64,
+    pub saturation: f64,
+    pub brightness: f64,
+    pub kelvin: u16,
+}
+
+impl LanDeviceState {
+    /*
+        Fields whose value differs from a previous state, as (field, old, new)
+    */
+    pub fn changes(&self, previous: &LanDeviceState) -> Vec<(String, Value, Value)> {
+        let (Value::Object(current), Value::Object(previous)) = (serde_json::json!(self), serde_json::json!(previous)) else {
+            return vec![];
+        };
+
+        current
+            .into_iter()
+            .filter(|(field, value)| previous.get(field) != Some(value))
+            .map(|(field, value)| {
+                let old = previous.get(&field).cloned().unwrap_or(Value::Null);
+                (field, old, value)
+            })
+            .collect()
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LanStateEvent {
+    pub timestamp: u64,
+    pub event: String,
+    pub id: String,
+    pub label: String,
+    /* Null unless the event is a change, so every event has the same fields */
+    pub field: Option<String>,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+// endregion: LanDeviceState
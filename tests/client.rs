@@ -0,0 +1,92 @@
+mod common;
+
+use std::{fs, process::{Command, Output}, time::{Duration, Instant}};
+
+use common::{command, config_with, lifx, light, stderr, stdout};
+use httpmock::{Method::GET, MockServer};
+use serde_json::json;
+
+fn lifx_with(server: &MockServer, name: &str, settings: &str, args: &[&str]) -> Output {
+    let config = config_with(name, settings);
+
+    let output = command(server, &config, args).output().unwrap();
+
+    fs::remove_dir_all(&config).unwrap();
+
+    output
+}
+
+#[test]
+fn user_agent_is_sent() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all").header("User-Agent", concat!("lifx-cli/", env!("CARGO_PKG_VERSION")));
+        then.status(200).json_body(json!([light("d073d5000001", "Kitchen")]));
+    });
+
+    let output = lifx(&server, "user-agent", &["lights", "list"]);
+
+    mock.assert();
+    assert!(output.status.success());
+}
+
+#[test]
+fn request_timeout_comes_from_config() {
+    let server = MockServer::start();
+
+    server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(200).delay(Duration::from_secs(3)).json_body(json!([]));
+    });
+
+    let started = Instant::now();
+    let output = lifx_with(&server, "timeout", "timeout: 0.5\n", &["lights", "list"]);
+
+    assert!(started.elapsed() < Duration::from_secs(3));
+    assert_eq!(output.status.code(), Some(8), "{}", stderr(&output));
+}
+
+#[test]
+fn invalid_timeout_is_a_config_error() {
+    let server = MockServer::start();
+
+    let output = lifx_with(&server, "timeout-invalid", "timeout: soon\n", &["lights", "list"]);
+
+    assert_eq!(output.status.code(), Some(12));
+    assert!(stderr(&output).contains("'timeout' must be a number of seconds"), "{}", stderr(&output));
+}
+
+#[test]
+fn requests_go_through_the_configured_proxy() {
+    let proxy = MockServer::start();
+
+    let mock = proxy.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(200).json_body(json!([light("d073d5000001", "Kitchen")]));
+    });
+
+    let config = config_with("proxy", &format!("proxy: {}\n", proxy.base_url()));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lifx"))
+        .args(["lights", "list"])
+        .env("XDG_CONFIG_HOME", &config)
+        .env("LIFX_API_URL", "http://lifx.invalid/v1")
+        .output()
+        .unwrap();
+
+    fs::remove_dir_all(&config).unwrap();
+
+    mock.assert();
+    assert!(stdout(&output).contains("Kitchen"), "{}", stderr(&output));
+}
+
+#[test]
+fn invalid_proxy_is_a_config_error() {
+    let server = MockServer::start();
+
+    let output = lifx_with(&server, "proxy-invalid", "proxy: not a url\n", &["lights", "list"]);
+
+    assert_eq!(output.status.code(), Some(12));
+    assert!(stderr(&output).contains("invalid proxy"), "{}", stderr(&output));
+}
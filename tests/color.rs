@@ -0,0 +1,190 @@
+mod common;
+
+use common::{lifx, stderr, stdout};
+use httpmock::{Method::GET, MockServer};
+use lifx_cli::color::Hsbk;
+use proptest::prelude::*;
+use serde_json::{json, Value};
+
+fn parse(color: &str) -> Hsbk {
+    color.parse().unwrap_or_else(|error| panic!("'{}' should parse: {}", color, error))
+}
+
+fn assert_close(actual: Option<f64>, expected: f64, color: &str) {
+    let actual = actual.unwrap_or_else(|| panic!("'{}' should set the component", color));
+    assert!((actual - expected).abs() < 0.01, "'{}': expected {}, got {}", color, expected, actual);
+}
+
+#[test]
+fn known_conversions() {
+    /* color, hue, saturation, brightness */
+    let cases = [
+        ("#ff0000", 0.0, 1.0, 1.0),
+        ("#00ff00", 120.0, 1.0, 1.0),
+        ("#0000ff", 240.0, 1.0, 1.0),
+        ("#ffff00", 60.0, 1.0, 1.0),
+        ("#ffffff", 0.0, 0.0, 1.0),
+        ("#000000", 0.0, 0.0, 0.0),
+        ("rgb:255,128,0", 30.12, 1.0, 1.0),
+        ("rgb:0,128,128", 180.0, 1.0, 0.502),
+        ("#FF00FF", 300.0, 1.0, 1.0),
+    ];
+
+    for (color, hue, saturation, brightness) in cases {
+        let hsbk = parse(color);
+
+        assert_close(hsbk.hue, hue, color);
+        assert_close(hsbk.saturation, saturation, color);
+        assert_close(hsbk.brightness, brightness, color);
+        assert_eq!(hsbk.kelvin, None);
+    }
+}
+
+#[test]
+fn named_colors() {
+    assert_eq!(parse("red"), Hsbk { hue: Some(0.0), saturation: Some(1.0), ..Default::default() });
+    assert_eq!(parse("blue"), Hsbk { hue: Some(250.0), saturation: Some(1.0), ..Default::default() });
+    assert_eq!(parse("White"), Hsbk { saturation: Some(0.0), ..Default::default() });
+}
+
+#[test]
+fn components_combine_and_later_ones_win() {
+    assert_eq!(
+        parse("red saturation:0.5 brightness:0.3"),
+        Hsbk { hue: Some(0.0), saturation: Some(0.5), brightness: Some(0.3), kelvin: None },
+    );
+
+    assert_eq!(
+        parse("kelvin:2700 hue:120 saturation:0.2"),
+        Hsbk { hue: Some(120.0), saturation: Some(0.2), brightness: None, kelvin: Some(2700.0) },
+    );
+
+    assert_eq!(parse("blue red").hue, Some(0.0));
+}
+
+#[test]
+fn kelvin_clears_saturation() {
+    assert_eq!(parse("kelvin:3500"), Hsbk { saturation: Some(0.0), kelvin: Some(3500.0), ..Default::default() });
+}
+
+#[test]
+fn invalid_colors() {
+    for color in ["", "   ", "bogus", "hue:361", "saturation:1.5", "brightness:-0.1", "kelvin:1000", "rgb:256,0,0", "rgb:1,2", "#ff00", "#gggggg", "hue:abc", "tint:0.5"] {
+        assert!(color.parse::<Hsbk>().is_err(), "'{}' should not parse", color);
+    }
+}
+
+#[test]
+fn every_invalid_component_is_reported() {
+    let error = "bogus hue:400 red".parse::<Hsbk>().unwrap_err();
+
+    assert_eq!(error.errors.len(), 2);
+}
+
+#[test]
+fn known_rgb_previews() {
+    assert_eq!(parse("#ff0000").to_rgb(), (255, 0, 0));
+    assert_eq!(parse("#00ff00").to_rgb(), (0, 255, 0));
+    assert_eq!(parse("white").to_rgb(), (255, 255, 255));
+    assert_eq!(parse("brightness:0").to_rgb(), (0, 0, 0));
+}
+
+#[test]
+fn command_previews_a_color_the_api_accepts() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/v1/color").query_param("string", "red brightness:0.5");
+        then.status(200).json_body(json!({ "hue": 0.0, "saturation": 1.0, "brightness": 0.5, "kelvin": null }));
+    });
+
+    let output = lifx(&server, "color-table", &["color", "red brightness:0.5"]);
+
+    mock.assert();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Saturation") && stdout(&output).contains("0.5"), "{}", stdout(&output));
+
+    let output = lifx(&server, "color-json", &["-o", "json", "color", "red brightness:0.5"]);
+
+    let color: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(color, json!({ "hue": 0.0, "saturation": 1.0, "brightness": 0.5, "kelvin": null }));
+}
+
+#[test]
+fn command_reports_a_color_the_api_rejects() {
+    let server = MockServer::start();
+
+    server.mock(|when, then| {
+        when.method(GET).path("/v1/color");
+        then.status(422).json_body(json!({
+            "error": "Validation error",
+            "errors": [{ "field": "string", "message": ["Unable to parse color: bogus"] }]
+        }));
+    });
+
+    let output = lifx(&server, "color-invalid", &["-o", "json", "color", "bogus"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("Unable to parse color: bogus"), "{}", stderr(&output));
+
+    let errors: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(errors["errors"][0]["field"], "string");
+}
+
+#[test]
+fn command_parses_offline_without_a_request() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.any_request();
+        then.status(500);
+    });
+
+    let output = lifx(&server, "color-offline", &["-o", "json", "color", "--offline", "kelvin:3500"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let color: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(color, json!({ "hue": null, "saturation": 0.0, "brightness": null, "kelvin": 3500.0 }));
+
+    let output = lifx(&server, "color-offline-invalid", &["color", "--offline", "hue:400 bogus"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("hue"), "{}", stderr(&output));
+    mock.assert_hits(0);
+}
+
+proptest! {
+    #[test]
+    fn hex_and_rgb_agree(r: u8, g: u8, b: u8) {
+        prop_assert_eq!(parse(&format!("#{:02x}{:02x}{:02x}", r, g, b)), parse(&format!("rgb:{},{},{}", r, g, b)));
+    }
+
+    #[test]
+    fn rgb_stays_in_range(r: u8, g: u8, b: u8) {
+        let hsbk = parse(&format!("rgb:{},{},{}", r, g, b));
+
+        prop_assert!((0.0..360.0).contains(&hsbk.hue.unwrap()));
+        prop_assert!((0.0..=1.0).contains(&hsbk.saturation.unwrap()));
+        prop_assert!((0.0..=1.0).contains(&hsbk.brightness.unwrap()));
+    }
+
+    #[test]
+    fn rgb_round_trips(r: u8, g: u8, b: u8) {
+        let (r2, g2, b2) = parse(&format!("rgb:{},{},{}", r, g, b)).to_rgb();
+
+        prop_assert!((r as i16 - r2 as i16).abs() <= 1, "red {} became {}", r, r2);
+        prop_assert!((g as i16 - g2 as i16).abs() <= 1, "green {} became {}", g, g2);
+        prop_assert!((b as i16 - b2 as i16).abs() <= 1, "blue {} became {}", b, b2);
+    }
+
+    #[test]
+    fn components_in_range_parse(hue in 0.0..=360.0f64, saturation in 0.0..=1.0f64, brightness in 0.0..=1.0f64, kelvin in 1500u16..=9000) {
+        let color = format!("kelvin:{} hue:{} saturation:{} brightness:{}", kelvin, hue, saturation, brightness);
+
+        prop_assert_eq!(
+            parse(&color),
+            Hsbk { hue: Some(hue), saturation: Some(saturation), brightness: Some(brightness), kelvin: Some(kelvin as f64) },
+        );
+    }
+}
@@ -0,0 +1,111 @@
+#![allow(dead_code)]
+
+use std::{fs, path::PathBuf, process::{Command, Output}};
+
+use httpmock::MockServer;
+use serde_json::{json, Value};
+
+pub const TOKEN: &str = "test-token";
+
+/*
+    A throwaway config directory holding only the API key
+*/
+pub fn config(name: &str) -> PathBuf {
+    config_with(name, "")
+}
+
+/*
+    A throwaway config directory holding the API key and extra YAML settings
+*/
+pub fn config_with(name: &str, settings: &str) -> PathBuf {
+    let config: PathBuf = std::env::temp_dir().join(format!("lifx-cli-test-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&config).unwrap();
+    fs::write(config.join("lifx-cli-config.yaml"), format!("api_key: {}\n{}", TOKEN, settings)).unwrap();
+    config
+}
+
+/*
+    The binary pointed at the mock server
+*/
+pub fn command(server: &MockServer, config: &PathBuf, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_lifx"));
+    command
+        .args(args)
+        .env("XDG_CONFIG_HOME", config)
+        .env("LIFX_API_URL", server.url("/v1"));
+    command
+}
+
+pub fn lifx(server: &MockServer, name: &str, args: &[&str]) -> Output {
+    let config = config(name);
+
+    let output = command(server, &config, args).output().unwrap();
+
+    fs::remove_dir_all(&config).unwrap();
+
+    output
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+pub fn light(id: &str, label: &str) -> Value {
+    json!({
+        "id": id,
+        "uuid": format!("uuid-{}", id),
+        "label": label,
+        "connected": true,
+        "power": "on",
+        "color": { "hue": 120.0, "saturation": 1.0, "kelvin": 3500 },
+        "brightness": 0.5,
+        "effect": "OFF",
+        "group": { "id": "g1", "name": "Office" },
+        "location": { "id": "l1", "name": "Home" },
+        "product": {
+            "name": "LIFX A19",
+            "identifier": "lifx_a19",
+            "company": "LIFX",
+            "vendor_id": 1,
+            "product_id": 27,
+            "capabilities": {
+                "has_color": true,
+                "has_variable_color_temp": true,
+                "has_ir": false,
+                "has_hev": false,
+                "has_chain": false,
+                "has_matrix": false,
+                "has_multizone": false,
+                "min_kelvin": 2500,
+                "max_kelvin": 9000
+            }
+        },
+        "last_seen": "2026-10-19T00:00:00Z",
+        "seconds_since_seen": 0
+    })
+}
+
+/* Every light reached the requested state */
+pub fn results() -> Value {
+    json!({
+        "results": [
+            { "id": "d073d5000001", "label": "Kitchen", "status": "ok" },
+            { "id": "d073d5000002", "label": "Desk", "status": "ok" }
+        ]
+    })
+}
+
+/* One light of each status */
+pub fn partial_results() -> Value {
+    json!({
+        "results": [
+            { "id": "d073d5000001", "label": "Kitchen", "status": "ok" },
+            { "id": "d073d5000002", "label": "Desk", "status": "timed_out" },
+            { "id": "d073d5000003", "label": "Porch", "status": "offline" }
+        ]
+    })
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+
+use common::{lifx, stderr};
+use httpmock::MockServer;
+use lifx_cli::lan::{packet::{self, BinarySerializable, LifxPacket, LightStateResponse, StateGroupingResponse, StateLabelResponse, StatePowerResponse, LIFX_PORT}, selector::Selector, Client};
+use tokio::net::UdpSocket;
+
+#[test]
+fn watch_rejects_intervals_that_arent_positive_numbers() {
+    let server = MockServer::start();
+
+    for interval in ["nan", "abc", "0", "-1", "inf"] {
+        let output = lifx(&server, "watch-interval", &["lan", "watch", &format!("--interval={}", interval)]);
+
+        assert_eq!(output.status.code(), Some(2), "{}: {}", interval, stderr(&output));
+        assert!(stderr(&output).contains("'interval' must be a number"), "{}", stderr(&output));
+    }
+}
+
+#[test]
+fn rate_is_validated_for_every_command_that_uses_the_lan() {
+    let server = MockServer::start();
+
+    let cases: &[&[&str]] = &[
+        &["lan", "--rate", "0", "discover"],
+        &["lights", "--transport", "lan", "--rate", "nan", "list"],
+        &["lights", "--transport", "auto", "--rate=-5", "toggle"],
+        &["--rate", "abc", "lights", "--transport", "lan", "list"],
+    ];
+
+    for args in cases {
+        let output = lifx(&server, "lan-rate", args);
+
+        assert_eq!(output.status.code(), Some(2), "{:?}: {}", args, stderr(&output));
+    }
+}
+
+struct Payload(Vec<u8>);
+
+impl BinarySerializable for Payload {
+    fn serialize(&self) -> Vec<u8> {
+        self.0.clone()
+    }
+}
+
+fn label(name: &str) -> [u8; 32] {
+    let mut label = [0; 32];
+    label[..name.len()].copy_from_slice(name.as_bytes());
+    label
+}
+
+/* A device on the LIFX port answering the queries used to describe it and read its state, counting each kind */
+async fn device(ip: &str) -> Arc<Mutex<HashMap<u16, usize>>> {
+    let socket = UdpSocket::bind((ip, LIFX_PORT)).await.unwrap();
+    let received = Arc::new(Mutex::new(HashMap::new()));
+    let counts = received.clone();
+    let serial = [0xd0, 0x73, 0xd5, 0, 0, 1];
+
+    tokio::spawn(async move {
+        let mut buffer = [0; 1024];
+
+        while let Ok((number_of_bytes, from)) = socket.recv_from(&mut buffer).await {
+            let (header, _) = packet::decode_packet(&buffer[..number_of_bytes]).unwrap();
+            *counts.lock().unwrap().entry(header.packet_type).or_insert(0) += 1;
+
+            let grouping = |name| bincode::serialize(&StateGroupingResponse { id: [1; 16], label: label(name), updated_at: 0 }).unwrap();
+
+            let (reply, payload) = match header.packet_type {
+                23 => (LifxPacket::StateLabel, bincode::serialize(&StateLabelResponse { label: label("Kitchen") }).unwrap()),
+                51 => (LifxPacket::StateGroup, grouping("Office")),
+                48 => (LifxPacket::StateLocation, grouping("Home")),
+                20 => (LifxPacket::StatePower, bincode::serialize(&StatePowerResponse { level: 65535 }).unwrap()),
+                101 => (LifxPacket::LightState, bincode::serialize(&LightStateResponse {
+                    hue: 0, saturation: 0, brightness: 65535, kelvin: 3500, reserved1: [0; 2], power: 65535, label: label("Kitchen"), reserved2: [0; 8],
+                }).unwrap()),
+                _ => continue,
+            };
+
+            let response = packet::encode_packet(header.sequence, reply, Some(serial), Some(&Payload(payload)));
+            socket.send_to(&response, from).await.unwrap();
+        }
+    });
+
+    received
+}
+
+#[tokio::test]
+async fn states_describe_a_device_once_then_only_poll_its_color_and_power() {
+    let received = device("127.0.0.1").await;
+    let client = Client::new(20.0).unwrap();
+    let ip = String::from("127.0.0.1");
+
+    for _ in 0..3 {
+        let states = client.states(&Selector::All, Some(&ip)).await.unwrap();
+
+        assert_eq!(states.len(), 1);
+        assert_eq!(states[0].id, "d073d5000001");
+    }
+
+    let received = received.lock().unwrap();
+
+    assert_eq!(received[&(LifxPacket::GetLabel as u16)], 1);
+    assert_eq!(received[&(LifxPacket::GetColor as u16)], 3);
+    assert_eq!(received[&(LifxPacket::GetPower as u16)], 3);
+}
+
+#[tokio::test]
+async fn states_report_a_device_at_an_ip_that_doesnt_answer() {
+    let client = Client::new(20.0).unwrap();
+
+    assert!(client.states(&Selector::All, Some(&String::from("127.0.0.2"))).await.is_err());
+}
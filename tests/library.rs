@@ -0,0 +1,61 @@
+mod common;
+
+use common::{light, partial_results, TOKEN};
+use httpmock::{Method::{GET, PUT}, MockServer};
+use lifx_cli::{cloud::{Client, ClientOptions}, controller::{LightController, LightState}, error::LifxError, types::LightResults};
+use serde_json::json;
+
+fn client(server: &MockServer) -> Client {
+    Client::new(TOKEN, &ClientOptions { base_url: server.url("/v1"), ..Default::default() }).unwrap()
+}
+
+#[tokio::test]
+async fn list_lights_returns_typed_lights() {
+    let server = MockServer::start_async().await;
+
+    server.mock_async(|when, then| {
+        when.method(GET).path("/v1/lights/group%3AOffice");
+        then.status(200).json_body(json!([light("d073d5000001", "Kitchen"), light("d073d5000002", "Desk")]));
+    }).await;
+
+    let lights = client(&server).list_lights("group:Office").await.unwrap();
+
+    assert_eq!(lights.len(), 2);
+    assert_eq!(lights[1].label, "Desk");
+    assert_eq!(lights[0].group.name, "Office");
+    assert!(lights[0].product.capabilities.has_color);
+}
+
+#[tokio::test]
+async fn set_state_returns_every_result_without_failing() {
+    let server = MockServer::start_async().await;
+
+    server.mock_async(|when, then| {
+        when.method(PUT).path("/v1/lights/all/state");
+        then.status(207).json_body(partial_results());
+    }).await;
+
+    let power = String::from("on");
+    let response = client(&server).set_state("all", Some(&power), None, None, None, None, None).await.unwrap();
+
+    assert_eq!(response.results.len(), 3);
+    assert!(matches!(response.check(), Err(LifxError::PartialFailure { failed: 2, total: 3 })));
+}
+
+#[tokio::test]
+async fn invalid_requests_are_rejected_before_sending() {
+    let server = MockServer::start_async().await;
+
+    let mock = server.mock_async(|when, then| {
+        when.any_request();
+        then.status(200);
+    }).await;
+
+    let controller: Box<dyn LightController> = Box::new(client(&server));
+    let state = LightState { color: Some(String::from("blurple")), ..Default::default() };
+
+    let error = controller.set_state("all", &state).await.unwrap_err();
+
+    mock.assert_hits_async(0).await;
+    assert_eq!(error.exit_code(), 2);
+}
@@ -0,0 +1,794 @@
+mod common;
+
+use std::{fs, process::Command};
+
+use common::{lifx, light, partial_results, results, stderr, stdout, TOKEN};
+use httpmock::{Method::{GET, POST, PUT}, MockServer};
+use serde_json::{json, Value};
+
+#[test]
+fn list_renders_a_table() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all").header("Authorization", format!("Bearer {}", TOKEN));
+        then.status(200).json_body(json!([light("d073d5000001", "Kitchen"), light("d073d5000002", "Desk")]));
+    });
+
+    let output = lifx(&server, "list", &["lights", "list"]);
+
+    mock.assert();
+    assert!(output.status.success());
+
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Kitchen"), "{}", stdout);
+    assert!(stdout.contains("Desk"), "{}", stdout);
+    assert!(stdout.contains("d073d5000002"), "{}", stdout);
+}
+
+#[test]
+fn list_raw_prints_json() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/label%3AKitchen");
+        then.status(200).json_body(json!([light("d073d5000001", "Kitchen")]));
+    });
+
+    let output = lifx(&server, "list-raw", &["--raw", "lights", "--selector", "label:Kitchen", "list"]);
+
+    mock.assert();
+
+    let lights: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(lights[0]["label"], "Kitchen");
+    assert_eq!(lights[0]["product"]["capabilities"]["has_color"], true);
+}
+
+#[test]
+fn list_csv_flattens_nested_fields() {
+    let server = MockServer::start();
+
+    server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(200).json_body(json!([light("d073d5000001", "Kitchen"), light("d073d5000002", "Desk, left")]));
+    });
+
+    let output = lifx(&server, "list-csv", &["lights", "list", "--output", "csv"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let stdout = stdout(&output);
+    let lines: Vec<&str> = stdout.lines().collect();
+    let columns: Vec<&str> = lines[0].split(',').collect();
+
+    assert_eq!(lines.len(), 3, "{}", stdout);
+    assert_eq!(&columns[..3], ["id", "uuid", "label"]);
+    assert!(columns.contains(&"group.name"), "{}", lines[0]);
+    assert!(columns.contains(&"product.capabilities.has_color"), "{}", lines[0]);
+    assert!(lines[2].starts_with(r#"d073d5000002,"#) && lines[2].contains(r#","Desk, left","#), "{}", lines[2]);
+}
+
+#[test]
+fn set_state_ndjson_prints_a_line_per_light() {
+    let server = MockServer::start();
+
+    server.mock(|when, then| {
+        when.method(PUT).path("/v1/lights/all/state");
+        then.status(207).json_body(partial_results());
+    });
+
+    let output = lifx(&server, "set-state-ndjson", &["-o", "ndjson", "lights", "set-state", "-p", "on"]);
+
+    assert_eq!(output.status.code(), Some(6));
+
+    let lines: Vec<Value> = stdout(&output).lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[1]["id"], "d073d5000002");
+    assert_eq!(lines[1]["status"], "timed_out");
+}
+
+#[test]
+fn list_yaml_prints_the_response() {
+    let server = MockServer::start();
+
+    server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(200).json_body(json!([light("d073d5000001", "Kitchen")]));
+    });
+
+    let output = lifx(&server, "list-yaml", &["lights", "list", "-o", "yaml"]);
+
+    let lights: serde_yaml::Value = serde_yaml::from_slice(&output.stdout).unwrap();
+    assert_eq!(lights[0]["label"], "Kitchen");
+    assert_eq!(lights[0]["group"]["name"], "Office");
+}
+
+#[test]
+fn list_filters_sorts_and_picks_columns() {
+    let server = MockServer::start();
+
+    let mut porch = light("d073d5000003", "Porch");
+    porch["power"] = json!("off");
+
+    let mut desk = light("d073d5000002", "Desk");
+    desk["brightness"] = json!(0.8);
+    desk["product"]["name"] = json!("LIFX Mini White");
+
+    server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(200).json_body(json!([light("d073d5000001", "Kitchen"), desk, porch]));
+    });
+
+    let output = lifx(&server, "list-view", &[
+        "lights", "list",
+        "--columns", "label,product,brightness",
+        "--sort", "-brightness",
+        "--where", "power=on",
+        "--where", "product!=LIFX Z",
+    ]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let stdout = stdout(&output);
+    let lines: Vec<&str> = stdout.lines().filter(|line| line.starts_with('|')).collect();
+
+    assert_eq!(lines.len(), 3, "{}", stdout);
+    assert!(lines[0].contains("Label") && lines[0].contains("Product") && !lines[0].contains("ID"), "{}", stdout);
+    assert!(lines[1].contains("Desk") && lines[1].contains("LIFX Mini White") && lines[1].contains("80%"), "{}", stdout);
+    assert!(lines[2].contains("Kitchen"), "{}", stdout);
+
+    let output = lifx(&server, "list-view-json", &["-o", "json", "lights", "list", "--where", "product~mini"]);
+
+    let lights: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(lights.as_array().unwrap().len(), 1);
+    assert_eq!(lights[0]["label"], "Desk");
+}
+
+#[test]
+fn list_rejects_unknown_fields_without_a_request() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.any_request();
+        then.status(200).json_body(json!([]));
+    });
+
+    let output = lifx(&server, "list-unknown-field", &["lights", "list", "--where", "colour=red"]);
+
+    mock.assert_hits(0);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("Unknown field 'colour'"), "{}", stderr(&output));
+}
+
+#[test]
+fn list_filters_on_inclusive_bounds() {
+    let server = MockServer::start();
+
+    let mut kitchen = light("d073d5000001", "Kitchen");
+    kitchen["brightness"] = json!(0.8);
+
+    let mut porch = light("d073d5000003", "Porch");
+    porch["brightness"] = json!(0.2);
+
+    /* The desk is at 0.5 */
+    server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(200).json_body(json!([kitchen, light("d073d5000002", "Desk"), porch]));
+    });
+
+    let labels = |filter: &str| {
+        let output = lifx(&server, "list-bounds", &["-o", "json", "lights", "list", "--where", filter]);
+        let lights: Value = serde_json::from_slice(&output.stdout).unwrap();
+
+        lights.as_array().unwrap().iter().map(|light| light["label"].as_str().unwrap().to_string()).collect::<Vec<String>>()
+    };
+
+    assert_eq!(labels("brightness>=0.5"), ["Kitchen", "Desk"]);
+    assert_eq!(labels("brightness<=0.5"), ["Desk", "Porch"]);
+}
+
+#[test]
+fn list_rejects_values_a_field_cant_be_compared_with() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.any_request();
+        then.status(200).json_body(json!([]));
+    });
+
+    for filter in ["brightness!=bright", "brightness>=high", "kelvin<nan", "connected=maybe"] {
+        let output = lifx(&server, "list-uncomparable", &["lights", "list", "--where", filter]);
+
+        assert_eq!(output.status.code(), Some(2), "{}: {}", filter, stderr(&output));
+        assert!(stderr(&output).contains("can't be compared"), "{}", stderr(&output));
+    }
+
+    mock.assert_hits(0);
+}
+
+#[test]
+fn toggle_posts_duration() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/v1/lights/group%3AOffice/toggle").json_body(json!({ "duration": "2.5" }));
+        then.status(207).json_body(results());
+    });
+
+    let output = lifx(&server, "toggle", &["lights", "-s", "group:Office", "toggle", "--duration", "2.5"]);
+
+    mock.assert();
+    assert!(output.status.success());
+
+    let stdout = stdout(&output);
+    assert!(stdout.contains("d073d5000001"), "{}", stdout);
+    assert!(stdout.contains("2 ok"), "{}", stdout);
+}
+
+#[test]
+fn partial_failures_are_summarized_and_exit_non_zero() {
+    let server = MockServer::start();
+
+    server.mock(|when, then| {
+        when.method(PUT).path("/v1/lights/all/state");
+        then.status(207).json_body(partial_results());
+    });
+
+    let output = lifx(&server, "partial", &["lights", "set-state", "-p", "on"]);
+
+    assert_eq!(output.status.code(), Some(6));
+    assert!(stdout(&output).contains("1 ok, 1 timed out, 1 offline"), "{}", stdout(&output));
+    assert!(stderr(&output).contains("2 of 3 lights failed"), "{}", stderr(&output));
+}
+
+#[test]
+fn allow_partial_exits_successfully() {
+    let server = MockServer::start();
+
+    server.mock(|when, then| {
+        when.method(POST).path("/v1/lights/all/toggle");
+        then.status(207).json_body(partial_results());
+    });
+
+    let output = lifx(&server, "allow-partial", &["lights", "toggle", "--allow-partial"]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("1 ok, 1 timed out, 1 offline"), "{}", stdout(&output));
+}
+
+#[test]
+fn retry_resends_state_to_timed_out_lights() {
+    let server = MockServer::start();
+
+    server.mock(|when, then| {
+        when.method(PUT).path("/v1/lights/all/state");
+        then.status(207).json_body(partial_results());
+    });
+
+    let retry = server.mock(|when, then| {
+        when.method(PUT)
+            .path("/v1/lights/id%3Ad073d5000002/state")
+            .json_body(json!({ "power": "on", "duration": 0.0 }));
+        then.status(207).json_body(json!({ "results": [{ "id": "d073d5000002", "label": "Desk", "status": "ok" }] }));
+    });
+
+    let output = lifx(&server, "retry", &["lights", "set-state", "-p", "on", "--retry", "2", "--verbose"]);
+
+    retry.assert_hits(1);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(stdout(&output).contains("2 ok, 1 offline"), "{}", stdout(&output));
+    assert!(stderr(&output).contains("Retrying 1 timed out light/s (attempt 1 of 2)"), "{}", stderr(&output));
+}
+
+#[test]
+fn retry_sets_timed_out_lights_to_the_toggled_power() {
+    let server = MockServer::start();
+
+    server.mock(|when, then| {
+        when.method(POST).path("/v1/lights/all/toggle");
+        then.status(207).json_body(json!({
+            "results": [
+                { "id": "d073d5000001", "label": "Kitchen", "status": "ok", "power": "off" },
+                { "id": "d073d5000002", "label": "Desk", "status": "timed_out" },
+                { "id": "d073d5000003", "label": "Porch", "status": "timed_out" }
+            ]
+        }));
+    });
+
+    let retry = server.mock(|when, then| {
+        when.method(PUT)
+            .path("/v1/lights/id%3Ad073d5000002%2Cid%3Ad073d5000003/state")
+            .json_body(json!({ "power": "off", "duration": 0.0 }));
+        then.status(207).json_body(json!({
+            "results": [
+                { "id": "d073d5000002", "label": "Desk", "status": "ok" },
+                { "id": "d073d5000003", "label": "Porch", "status": "timed_out" }
+            ]
+        }));
+    });
+
+    let porch = server.mock(|when, then| {
+        when.method(PUT)
+            .path("/v1/lights/id%3Ad073d5000003/state")
+            .json_body(json!({ "power": "off", "duration": 0.0 }));
+        then.status(207).json_body(json!({ "results": [{ "id": "d073d5000003", "label": "Porch", "status": "timed_out" }] }));
+    });
+
+    let output = lifx(&server, "retry-toggle", &["lights", "toggle", "--retry", "3"]);
+
+    retry.assert_hits(1);
+    porch.assert_hits(2);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(stdout(&output).contains("2 ok, 1 timed out"), "{}", stdout(&output));
+}
+
+#[test]
+fn cloud_only_commands_are_rejected_over_lan() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.any_request();
+        then.status(200);
+    });
+
+    let output = lifx(&server, "lan-only", &["lights", "--transport", "lan", "adjust", "-b", "0.1"]);
+
+    mock.assert_hits(0);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("'adjust' is only available over the cloud API"), "{}", stderr(&output));
+
+    let output = lifx(&server, "lan-only-effect", &["lights", "--transport", "lan", "effect", "flame"]);
+
+    mock.assert_hits(0);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("'effect' is only available over the cloud API"), "{}", stderr(&output));
+}
+
+#[test]
+fn auto_sends_lights_missing_from_the_lan_to_the_cloud() {
+    let server = MockServer::start();
+
+    let list = server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(200).json_body(json!([light("d073d5000001", "Kitchen"), light("d073d5000002", "Desk")]));
+    });
+
+    let state = server.mock(|when, then| {
+        when.method(PUT).path("/v1/lights/id%3Ad073d5000001%2Cid%3Ad073d5000002/state").json_body_partial(r#"{ "power": "on" }"#);
+        then.status(207).json_body(results());
+    });
+
+    let output = lifx(&server, "auto", &["lights", "--transport", "auto", "set-state", "--power", "on"]);
+
+    list.assert();
+    state.assert();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("cloud"), "{}", stdout(&output));
+}
+
+#[test]
+fn auto_sends_waveforms_to_the_cloud_once_when_the_lan_is_unreachable() {
+    let server = MockServer::start();
+
+    server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(200).json_body(json!([light("d073d5000001", "Kitchen"), light("d073d5000002", "Desk")]));
+    });
+
+    let breathe = server.mock(|when, then| {
+        when.method(POST).path("/v1/lights/id%3Ad073d5000001%2Cid%3Ad073d5000002/effects/breathe");
+        then.status(207).json_body(partial_results());
+    });
+
+    let output = lifx(&server, "auto-breathe", &["--raw", "--allow-partial", "lights", "--transport", "auto", "breathe", "-c", "red"]);
+
+    breathe.assert_hits(1);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    /* The light that timed out is reported rather than sent the waveform again, and the stray Porch result is dropped */
+    let response: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let results = response["results"].as_array().unwrap();
+
+    assert_eq!(results.len(), 2, "{}", response);
+    assert!(results.iter().all(|result| result["transport"] == "cloud"), "{}", response);
+    assert_eq!(results[1]["status"], "timed_out");
+}
+
+#[test]
+fn partial_failures_in_set_states_exit_non_zero() {
+    let server = MockServer::start();
+
+    let path = std::env::temp_dir().join(format!("lifx-cli-test-{}-partial-states.json", std::process::id()));
+    fs::write(&path, r#"[{ "selector": "group:Office", "power": "on" }]"#).unwrap();
+
+    server.mock(|when, then| {
+        when.method(PUT).path("/v1/lights/states");
+        then.status(207).json_body(json!({
+            "results": [{
+                "operation": { "selector": "group:Office", "power": "on" },
+                "results": partial_results()["results"]
+            }]
+        }));
+    });
+
+    let output = lifx(&server, "partial-states", &["--raw", "lights", "set-states", path.to_str().unwrap()]);
+
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(6));
+
+    let response: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(response["results"][0]["results"][2]["status"], "offline");
+}
+
+#[test]
+fn set_state_puts_state() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.method(PUT).path("/v1/lights/all/state").json_body(json!({
+            "power": "on",
+            "color": "red saturation:0.5",
+            "brightness": 0.4,
+            "duration": 1.0
+        }));
+        then.status(207).json_body(results());
+    });
+
+    let output = lifx(&server, "set-state", &["lights", "set-state", "-p", "on", "-c", "red saturation:0.5", "-b", "0.4", "-d", "1"]);
+
+    mock.assert();
+    assert!(stdout(&output).contains("Kitchen"));
+}
+
+#[test]
+fn set_state_rejects_invalid_color_without_a_request() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.path_contains("/v1/");
+        then.status(500);
+    });
+
+    let output = lifx(&server, "set-state-invalid", &["lights", "set-state", "-c", "hue:400"]);
+
+    mock.assert_hits(0);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("'hue' must be a number between 0 and 360"), "{}", stderr(&output));
+}
+
+#[test]
+fn values_that_dont_parse_are_rejected_without_a_request() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.path_contains("/v1/");
+        then.status(500);
+    });
+
+    let cases: &[&[&str]] = &[
+        &["lights", "set-state", "-b", "abc"],
+        &["lights", "clean", "-d", "abc"],
+        &["lights", "clean", "-d", "1.5"],
+        &["lights", "toggle", "-d", "soon"],
+        &["lights", "adjust", "--kelvin", "warm"],
+        &["lights", "pulse", "-c", "red", "--power-on", "yes"],
+        &["lights", "effect", "flame", "--period", "fast"],
+        &["scenes", "activate", "Evening", "-d", "abc"],
+    ];
+
+    for args in cases {
+        let output = lifx(&server, "unparseable", args);
+
+        assert_eq!(output.status.code(), Some(2), "{:?}: {}", args, stderr(&output));
+        assert!(stderr(&output).contains("must be"), "{:?}: {}", args, stderr(&output));
+    }
+
+    mock.assert_hits(0);
+}
+
+#[test]
+fn numbers_that_arent_finite_are_rejected_without_a_request() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.path_contains("/v1/");
+        then.status(500);
+    });
+
+    let cases: &[&[&str]] = &[
+        &["lights", "set-state", "-b", "nan"],
+        &["lights", "set-state", "-d", "inf"],
+        &["lights", "set-state", "--infrared", "NaN"],
+        &["lights", "adjust", "-d", "nan"],
+        &["lights", "adjust", "--hue", "nan"],
+        &["lights", "adjust", "--brightness=-inf"],
+        &["lights", "breathe", "-c", "red", "--period", "nan"],
+        &["lights", "pulse", "-c", "red", "--cycles", "inf"],
+        &["lights", "--transport", "lan", "breathe", "-c", "red", "--cycles", "nan"],
+        &["lights", "breathe", "-c", "red", "--peak", "nan"],
+        &["lights", "effect", "flame", "--period", "nan"],
+        &["lights", "effect", "move", "--cycles", "inf"],
+        &["lights", "effect", "clouds", "--duration", "NaN"],
+        &["scenes", "activate", "Evening", "-d", "nan"],
+        &["scenes", "activate", "Evening", "--duration=-1"],
+    ];
+
+    for args in cases {
+        let output = lifx(&server, "not-finite", args);
+
+        assert_eq!(output.status.code(), Some(2), "{:?}: {}", args, stderr(&output));
+    }
+
+    mock.assert_hits(0);
+}
+
+#[test]
+fn set_state_sends_fast_only_when_asked() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.method(PUT).path("/v1/lights/all/state").json_body_partial(r#"{ "power": "off", "fast": true }"#);
+        then.status(202);
+    });
+
+    let output = lifx(&server, "set-state-fast", &["lights", "set-state", "-p", "off", "--fast"]);
+
+    mock.assert();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("no per-light results"), "{}", stdout(&output));
+}
+
+#[test]
+fn fast_set_states_accepts_an_empty_response() {
+    let server = MockServer::start();
+
+    let path = std::env::temp_dir().join(format!("lifx-cli-test-{}-fast-states.json", std::process::id()));
+    fs::write(&path, r#"[{ "selector": "label:Kitchen", "power": "on" }]"#).unwrap();
+
+    let mock = server.mock(|when, then| {
+        when.method(PUT).path("/v1/lights/states").json_body_partial(r#"{ "fast": true }"#);
+        then.status(202);
+    });
+
+    let output = lifx(&server, "set-states-fast", &["--raw", "lights", "set-states", path.to_str().unwrap(), "--fast"]);
+
+    fs::remove_file(&path).unwrap();
+
+    mock.assert();
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let response: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(response["results"], json!([]));
+}
+
+#[test]
+fn adjust_posts_deltas() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/v1/lights/id%3Ad073d5000001/state/delta").json_body(json!({
+            "brightness": -0.2,
+            "duration": 0.0,
+            "hue": 30.0
+        }));
+        then.status(207).json_body(results());
+    });
+
+    let output = lifx(&server, "adjust", &["lights", "-s", "id:d073d5000001", "adjust", "--brightness", "-0.2", "--hue", "30"]);
+
+    mock.assert();
+    assert!(output.status.success());
+}
+
+#[test]
+fn set_states_puts_file_contents() {
+    let server = MockServer::start();
+
+    let path = std::env::temp_dir().join(format!("lifx-cli-test-{}-states.json", std::process::id()));
+    fs::write(&path, r#"[{ "selector": "label:Kitchen", "power": "on" }]"#).unwrap();
+
+    let mock = server.mock(|when, then| {
+        when.method(PUT).path("/v1/lights/states").json_body(json!({
+            "states": [{ "selector": "label:Kitchen", "power": "on" }]
+        }));
+        then.status(207).json_body(json!({
+            "results": [{
+                "operation": { "selector": "label:Kitchen", "power": "on" },
+                "results": [{ "id": "d073d5000001", "label": "Kitchen", "status": "ok" }]
+            }]
+        }));
+    });
+
+    let output = lifx(&server, "set-states", &["lights", "set-states", path.to_str().unwrap()]);
+
+    fs::remove_file(&path).unwrap();
+
+    mock.assert();
+    assert!(stdout(&output).contains("label:Kitchen"));
+}
+
+#[test]
+fn cycle_posts_states_defaults_and_direction() {
+    let server = MockServer::start();
+
+    let path = std::env::temp_dir().join(format!("lifx-cli-test-{}-cycle.yaml", std::process::id()));
+    fs::write(&path, "states:\n  - color: red\n  - color: blue\n    brightness: 0.5\ndefaults:\n  duration: 2\n  power: on\n").unwrap();
+
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/v1/lights/group%3AOffice/cycle").json_body(json!({
+            "states": [{ "color": "red" }, { "color": "blue", "brightness": 0.5 }],
+            "defaults": { "duration": 2, "power": "on" },
+            "direction": "backward"
+        }));
+        then.status(207).json_body(results());
+    });
+
+    let output = lifx(&server, "cycle", &["lights", "-s", "group:Office", "cycle", "--states", path.to_str().unwrap(), "--direction", "backward"]);
+
+    fs::remove_file(&path).unwrap();
+
+    mock.assert();
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn effect_is_refused_for_lights_without_the_capability() {
+    let server = MockServer::start();
+
+    let lights = server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(200).json_body(json!([light("d073d5000001", "Kitchen")]));
+    });
+
+    let effect = server.mock(|when, then| {
+        when.method(POST).path("/v1/lights/all/effects/flame");
+        then.status(207).json_body(results());
+    });
+
+    let output = lifx(&server, "effect", &["lights", "effect", "flame"]);
+
+    lights.assert();
+    effect.assert_hits(0);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("without matrix support"), "{}", stderr(&output));
+}
+
+#[test]
+fn clean_starts_and_stops_a_cycle() {
+    let server = MockServer::start();
+
+    let mut bulb = light("d073d5000001", "Clean");
+    bulb["product"]["capabilities"]["has_hev"] = json!(true);
+
+    server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(200).json_body(json!([bulb]));
+    });
+
+    let start = server.mock(|when, then| {
+        when.method(POST).path("/v1/lights/all/clean").json_body(json!({ "stop": false, "duration": 86400 }));
+        then.status(207).json_body(results());
+    });
+
+    let stop = server.mock(|when, then| {
+        when.method(POST).path("/v1/lights/all/clean").json_body(json!({ "stop": true }));
+        then.status(207).json_body(results());
+    });
+
+    let output = lifx(&server, "clean-start", &["lights", "clean", "-d", "86400"]);
+
+    start.assert();
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = lifx(&server, "clean-stop", &["lights", "clean", "--stop"]);
+
+    stop.assert();
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn clean_rejects_long_durations_without_a_request() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.any_request();
+        then.status(200).json_body(json!([]));
+    });
+
+    let output = lifx(&server, "clean-too-long", &["lights", "clean", "-d", "86401"]);
+
+    mock.assert_hits(0);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("between 0 and 86400"), "{}", stderr(&output));
+}
+
+#[test]
+fn clean_is_refused_for_lights_without_hev() {
+    let server = MockServer::start();
+
+    let lights = server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(200).json_body(json!([light("d073d5000001", "Kitchen")]));
+    });
+
+    let clean = server.mock(|when, then| {
+        when.method(POST).path("/v1/lights/all/clean");
+        then.status(207).json_body(results());
+    });
+
+    let output = lifx(&server, "clean-unsupported", &["lights", "clean"]);
+
+    lights.assert();
+    clean.assert_hits(0);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("without HEV (Clean) support"), "{}", stderr(&output));
+}
+
+#[test]
+fn fast_effects_accept_an_empty_response() {
+    let server = MockServer::start();
+
+    let mut tile = light("d073d5000001", "Tile");
+    tile["product"]["capabilities"]["has_matrix"] = json!(true);
+
+    server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(200).json_body(json!([tile]));
+    });
+
+    let effect = server.mock(|when, then| {
+        when.method(POST).path("/v1/lights/all/effects/flame").json_body_partial(r#"{ "period": 5.0, "fast": true }"#);
+        then.status(202);
+    });
+
+    let output = lifx(&server, "effect-fast", &["lights", "effect", "flame", "--period", "5", "--fast"]);
+
+    effect.assert();
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn error_statuses_map_to_exit_codes() {
+    let cases = [
+        (401, json!({ "error": "Invalid token" }), 3, "lifx auth clear"),
+        (404, json!({ "error": "Could not find light with selector 'label:Nope'" }), 4, "Could not find light"),
+        (422, json!({ "error": "Validation error", "errors": [{ "field": "brightness", "message": ["must be at most 1.0"] }] }), 2, "brightness: must be at most 1.0"),
+    ];
+
+    for (status, body, code, message) in cases {
+        let server = MockServer::start();
+
+        server.mock(|when, then| {
+            when.method(GET).path("/v1/lights/all");
+            then.status(status).json_body(body);
+        });
+
+        let output = lifx(&server, &format!("status-{}", status), &["lights", "list"]);
+
+        assert_eq!(output.status.code(), Some(code), "status {}", status);
+        assert!(stderr(&output).contains(message), "status {}: {}", status, stderr(&output));
+    }
+}
+
+#[test]
+fn unreachable_api_is_a_network_error() {
+    let server = MockServer::start();
+
+    let config = std::env::temp_dir().join(format!("lifx-cli-test-{}-unreachable", std::process::id()));
+    fs::create_dir_all(&config).unwrap();
+    fs::write(config.join("lifx-cli-config.yaml"), format!("api_key: {}\napi_url: {}\n", TOKEN, server.url("/v1"))).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lifx"))
+        .args(["lights", "list"])
+        .env("XDG_CONFIG_HOME", &config)
+        .env("LIFX_API_URL", "http://127.0.0.1:1/v1")
+        .output()
+        .unwrap();
+
+    fs::remove_dir_all(&config).unwrap();
+
+    assert_eq!(output.status.code(), Some(8));
+    assert!(stderr(&output).contains("Unable to reach the LIFX API"), "{}", stderr(&output));
+}
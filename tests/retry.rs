@@ -0,0 +1,180 @@
+mod common;
+
+use std::{fs, process::Stdio, thread, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+use common::{command, config, lifx, light, stderr, stdout};
+use httpmock::{Method::{GET, POST}, MockServer};
+use serde_json::json;
+
+/* Including the first attempt */
+const MAX_ATTEMPTS: usize = 4;
+
+#[test]
+fn server_errors_are_retried_then_reported() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(503).json_body(json!({ "error": "Service unavailable" }));
+    });
+
+    let output = lifx(&server, "retry-503", &["lights", "list"]);
+
+    mock.assert_hits(MAX_ATTEMPTS);
+    assert_eq!(output.status.code(), Some(9));
+    assert!(stderr(&output).contains("Service unavailable"), "{}", stderr(&output));
+}
+
+#[test]
+fn rate_limited_requests_are_retried_then_reported() {
+    let server = MockServer::start();
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(429)
+            .header("X-RateLimit-Limit", "120")
+            .header("X-RateLimit-Remaining", "0")
+            .header("X-RateLimit-Reset", now.to_string())
+            .json_body(json!({ "error": "Rate limit exceeded" }));
+    });
+
+    let output = lifx(&server, "retry-429", &["lights", "list"]);
+
+    mock.assert_hits(MAX_ATTEMPTS);
+    assert_eq!(output.status.code(), Some(5));
+    assert!(stderr(&output).contains("Rate limited"), "{}", stderr(&output));
+}
+
+#[test]
+fn rate_limits_resetting_much_later_fail_straight_away() {
+    let server = MockServer::start();
+
+    let reset = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 3600;
+
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(429)
+            .header("X-RateLimit-Reset", reset.to_string())
+            .json_body(json!({ "error": "Rate limit exceeded" }));
+    });
+
+    let started = Instant::now();
+    let output = lifx(&server, "retry-429-later", &["lights", "list"]);
+
+    mock.assert_hits(1);
+    assert!(started.elapsed() < Duration::from_secs(10), "waited {:?}", started.elapsed());
+    assert_eq!(output.status.code(), Some(5));
+    assert!(stderr(&output).contains("try again in"), "{}", stderr(&output));
+}
+
+#[test]
+fn client_errors_are_not_retried() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(404).json_body(json!({ "error": "Could not find light" }));
+    });
+
+    let output = lifx(&server, "retry-404", &["lights", "list"]);
+
+    mock.assert_hits(1);
+    assert_eq!(output.status.code(), Some(4));
+}
+
+#[test]
+fn server_errors_on_non_idempotent_requests_are_not_retried() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/v1/lights/all/toggle");
+        then.status(503).json_body(json!({ "error": "Service unavailable" }));
+    });
+
+    let output = lifx(&server, "retry-post-503", &["lights", "toggle"]);
+
+    /* The toggle may have happened before the error, sending it again could switch the lights back */
+    mock.assert_hits(1);
+    assert_eq!(output.status.code(), Some(9));
+}
+
+#[test]
+fn rate_limited_non_idempotent_requests_are_retried() {
+    let server = MockServer::start();
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/v1/lights/all/toggle");
+        then.status(429)
+            .header("X-RateLimit-Reset", now.to_string())
+            .json_body(json!({ "error": "Rate limit exceeded" }));
+    });
+
+    let output = lifx(&server, "retry-post-429", &["lights", "toggle"]);
+
+    mock.assert_hits(MAX_ATTEMPTS);
+    assert_eq!(output.status.code(), Some(5));
+}
+
+#[test]
+fn retries_until_the_api_recovers() {
+    let server = MockServer::start();
+
+    let mut failing = server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(502);
+    });
+
+    let config = config("retry-recovers");
+
+    let child = command(&server, &config, &["lights", "list"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    /* Swap in a working endpoint while the CLI backs off after its first attempt */
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while failing.hits() == 0 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    failing.delete();
+
+    let working = server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(200).json_body(json!([light("d073d5000001", "Kitchen")]));
+    });
+
+    let output = child.wait_with_output().unwrap();
+
+    fs::remove_dir_all(&config).unwrap();
+
+    working.assert_hits(1);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Kitchen"));
+}
+
+#[test]
+fn verbose_reports_remaining_quota() {
+    let server = MockServer::start();
+
+    let reset = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 30;
+
+    server.mock(|when, then| {
+        when.method(GET).path("/v1/lights/all");
+        then.status(200)
+            .header("X-RateLimit-Limit", "120")
+            .header("X-RateLimit-Remaining", "119")
+            .header("X-RateLimit-Reset", reset.to_string())
+            .json_body(json!([light("d073d5000001", "Kitchen")]));
+    });
+
+    let output = lifx(&server, "verbose", &["lights", "list", "--verbose"]);
+
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("119/120 requests remaining"), "{}", stderr(&output));
+}
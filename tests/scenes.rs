@@ -0,0 +1,163 @@
+mod common;
+
+use common::{lifx, results, stderr, stdout};
+use httpmock::{Method::{GET, PUT}, MockServer};
+use serde_json::{json, Value};
+
+fn scene(uuid: &str, name: &str) -> Value {
+    json!({
+        "uuid": uuid,
+        "name": name,
+        "account": { "uuid": "account-uuid" },
+        "states": [{
+            "selector": "id:d073d5000001",
+            "power": "on",
+            "brightness": 0.4,
+            "color": { "hue": 30.0, "saturation": 0.5, "kelvin": 3000 }
+        }],
+        "created_at": 1700000000,
+        "updated_at": 1700000500
+    })
+}
+
+fn scenes(server: &MockServer) -> httpmock::Mock<'_> {
+    server.mock(|when, then| {
+        when.method(GET).path("/v1/scenes");
+        then.status(200).json_body(json!([
+            scene("0b0b0b0b-0000-4000-8000-000000000001", "Evening"),
+            scene("0b0b0b0b-0000-4000-8000-000000000002", "Reading"),
+            scene("0b0b0b0b-0000-4000-8000-000000000003", "reading"),
+        ]));
+    })
+}
+
+#[test]
+fn list_prints_every_scene() {
+    let server = MockServer::start();
+    let list = scenes(&server);
+
+    let output = lifx(&server, "scenes-list", &["scenes", "list"]);
+
+    list.assert();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Evening"), "{}", stdout(&output));
+    assert!(stdout(&output).contains("0b0b0b0b-0000-4000-8000-000000000002"), "{}", stdout(&output));
+}
+
+#[test]
+fn activate_finds_the_scene_by_name_ignoring_case() {
+    let server = MockServer::start();
+    scenes(&server);
+
+    let activate = server.mock(|when, then| {
+        when.method(PUT).path("/v1/scenes/scene_id:0b0b0b0b-0000-4000-8000-000000000001/activate").json_body(json!({ "duration": 2.0 }));
+        then.status(207).json_body(results());
+    });
+
+    let output = lifx(&server, "scenes-activate-name", &["scenes", "activate", "EVENING", "-d", "2"]);
+
+    activate.assert();
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn activate_prefers_an_exact_uuid() {
+    let server = MockServer::start();
+    scenes(&server);
+
+    let activate = server.mock(|when, then| {
+        when.method(PUT).path("/v1/scenes/scene_id:0b0b0b0b-0000-4000-8000-000000000002/activate");
+        then.status(207).json_body(results());
+    });
+
+    let output = lifx(&server, "scenes-activate-uuid", &["scenes", "activate", "0b0b0b0b-0000-4000-8000-000000000002"]);
+
+    activate.assert();
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn activate_refuses_ambiguous_and_unknown_names() {
+    let server = MockServer::start();
+    scenes(&server);
+
+    let activate = server.mock(|when, then| {
+        when.method(PUT).path_contains("/activate");
+        then.status(207).json_body(results());
+    });
+
+    let ambiguous = lifx(&server, "scenes-ambiguous", &["scenes", "activate", "Reading"]);
+
+    assert_eq!(ambiguous.status.code(), Some(2));
+    assert!(stderr(&ambiguous).contains("0b0b0b0b-0000-4000-8000-000000000003"), "{}", stderr(&ambiguous));
+
+    let unknown = lifx(&server, "scenes-unknown", &["scenes", "activate", "Morning"]);
+
+    assert_eq!(unknown.status.code(), Some(4));
+    activate.assert_hits(0);
+}
+
+#[test]
+fn activate_sends_ignored_fields_and_overrides() {
+    let server = MockServer::start();
+    scenes(&server);
+
+    let activate = server.mock(|when, then| {
+        when.method(PUT).path("/v1/scenes/scene_id:0b0b0b0b-0000-4000-8000-000000000001/activate").json_body(json!({
+            "ignore": ["power", "hue", "saturation", "kelvin"],
+            "overrides": { "brightness": 0.5, "color": "red" }
+        }));
+        then.status(207).json_body(results());
+    });
+
+    let output = lifx(&server, "scenes-ignore", &[
+        "scenes", "activate", "Evening",
+        "--ignore", "power,color,hue",
+        "--overrides", "brightness=0.5",
+        "--overrides", "color=red",
+    ]);
+
+    activate.assert();
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn activate_rejects_bad_input_without_a_request() {
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.path_contains("/v1/");
+        then.status(500);
+    });
+
+    let cases: &[&[&str]] = &[
+        &["scenes", "activate", "Evening", "--ignore", "power,label"],
+        &["scenes", "activate", "Evening", "--overrides", "brightness"],
+        &["scenes", "activate", "Evening", "--overrides", "brightness=2"],
+        &["scenes", "activate", "Evening", "--overrides", "color=nope"],
+    ];
+
+    for args in cases {
+        let output = lifx(&server, "scenes-invalid", args);
+
+        assert_eq!(output.status.code(), Some(2), "{:?}: {}", args, stderr(&output));
+    }
+
+    mock.assert_hits(0);
+}
+
+#[test]
+fn fast_activation_accepts_an_empty_response() {
+    let server = MockServer::start();
+    scenes(&server);
+
+    let activate = server.mock(|when, then| {
+        when.method(PUT).path("/v1/scenes/scene_id:0b0b0b0b-0000-4000-8000-000000000001/activate").json_body(json!({ "fast": true }));
+        then.status(202);
+    });
+
+    let output = lifx(&server, "scenes-fast", &["scenes", "activate", "Evening", "--fast"]);
+
+    activate.assert();
+    assert!(output.status.success(), "{}", stderr(&output));
+}
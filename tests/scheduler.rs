@@ -0,0 +1,178 @@
+use std::net::SocketAddr;
+
+use lifx_cli::lan::{packet::{self, LifxPacket, SetColorPayload}, scheduler::{Outcome, Scheduler, SchedulerReport}};
+use tokio::{net::UdpSocket, sync::{mpsc, oneshot}, time::Instant};
+
+/* A device that never answers, so every packet sent to it times out */
+async fn silent_device() -> (UdpSocket, SocketAddr) {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap();
+
+    (socket, address)
+}
+
+/* A device that answers every packet with `reply`, passing the type of each packet it receives to the test */
+async fn answering_device(reply: LifxPacket) -> (SocketAddr, mpsc::UnboundedReceiver<u16>) {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap();
+    let (received, receiver) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let mut buffer = [0; 1024];
+
+        while let Ok((number_of_bytes, from)) = socket.recv_from(&mut buffer).await {
+            let (header, _) = packet::decode_packet(&buffer[..number_of_bytes]).unwrap();
+            let _ = received.send(header.packet_type);
+
+            socket.send_to(&packet::encode_packet(header.sequence, reply, None, None), from).await.unwrap();
+        }
+    });
+
+    (address, receiver)
+}
+
+fn get_power(sequence: u8) -> Vec<u8> {
+    packet::encode_packet(sequence, LifxPacket::GetPower, None, None)
+}
+
+fn set_color(sequence: u8, hue: f64) -> Vec<u8> {
+    packet::encode_packet(sequence, LifxPacket::SetColor, None, Some(&SetColorPayload::new(hue, 1.0, 1.0, 3500, 0)))
+}
+
+async fn outcomes(receivers: Vec<oneshot::Receiver<Outcome>>) -> Vec<Outcome> {
+    let mut outcomes = vec![];
+
+    for receiver in receivers {
+        outcomes.push(receiver.await.unwrap());
+    }
+
+    outcomes
+}
+
+#[test]
+fn rates_that_arent_positive_numbers_are_rejected() {
+    for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+        assert!(Scheduler::new(rate).is_err(), "{} should be rejected", rate);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn packets_to_a_device_are_spaced_by_the_rate() {
+    let (_device, address) = silent_device().await;
+    let scheduler = Scheduler::new(20.0).unwrap();
+
+    let started = Instant::now();
+    let receivers = (0..3).map(|sequence| scheduler.enqueue(address, LifxPacket::GetPower, get_power(sequence))).collect();
+
+    let report = scheduler.flush().await;
+
+    assert_eq!(outcomes(receivers).await, vec![Outcome::TimedOut; 3]);
+    assert_eq!(report, SchedulerReport { sent: 3, timed_out: 3, delayed: 2, max_delay_ms: 100, ..Default::default() });
+
+    /* The last packet goes out two intervals after the first and then waits a second for an acknowledgement */
+    assert!(started.elapsed().as_millis() >= 1100, "{:?}", started.elapsed());
+}
+
+#[tokio::test(start_paused = true)]
+async fn devices_are_rate_limited_independently() {
+    let (_kitchen, kitchen) = silent_device().await;
+    let (_porch, porch) = silent_device().await;
+    let scheduler = Scheduler::new(20.0).unwrap();
+
+    for (sequence, address) in [kitchen, porch, kitchen, porch].into_iter().enumerate() {
+        drop(scheduler.enqueue(address, LifxPacket::GetPower, get_power(sequence as u8)));
+    }
+
+    let report = scheduler.flush().await;
+
+    assert_eq!(report.sent, 4);
+    assert_eq!(report.delayed, 2);
+    assert_eq!(report.max_delay_ms, 50);
+}
+
+#[tokio::test(start_paused = true)]
+async fn queued_state_writes_are_coalesced() {
+    let (_device, address) = silent_device().await;
+    let scheduler = Scheduler::new(20.0).unwrap();
+
+    let receivers = vec![
+        scheduler.enqueue(address, LifxPacket::SetColor, set_color(0, 0.0)),
+        scheduler.enqueue(address, LifxPacket::SetColor, set_color(1, 120.0)),
+        scheduler.enqueue(address, LifxPacket::GetPower, get_power(2)),
+        scheduler.enqueue(address, LifxPacket::SetColor, set_color(3, 240.0)),
+    ];
+
+    let report = scheduler.flush().await;
+
+    assert_eq!(outcomes(receivers).await, vec![Outcome::Coalesced, Outcome::Coalesced, Outcome::TimedOut, Outcome::TimedOut]);
+    assert_eq!(report.coalesced, 2);
+    assert_eq!(report.sent, 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn superseding_writes_are_sent_after_packets_queued_before_them() {
+    let (address, mut received) = answering_device(LifxPacket::Acknowledgement).await;
+    let scheduler = Scheduler::new(20.0).unwrap();
+
+    let receivers = vec![
+        scheduler.enqueue(address, LifxPacket::SetColor, set_color(0, 0.0)),
+        scheduler.enqueue(address, LifxPacket::GetPower, get_power(1)),
+        scheduler.enqueue(address, LifxPacket::SetColor, set_color(2, 120.0)),
+    ];
+
+    scheduler.flush().await;
+
+    assert_eq!(outcomes(receivers).await, vec![Outcome::Coalesced, Outcome::Acknowledged, Outcome::Acknowledged]);
+    assert_eq!(received.recv().await, Some(LifxPacket::GetPower as u16));
+    assert_eq!(received.recv().await, Some(LifxPacket::SetColor as u16));
+}
+
+#[tokio::test(start_paused = true)]
+async fn queries_share_the_rate_limit_and_resolve_with_the_response() {
+    let (address, _received) = answering_device(LifxPacket::StatePower).await;
+    let scheduler = Scheduler::new(20.0).unwrap();
+
+    let started = Instant::now();
+    let receivers = (0..3).map(|sequence| scheduler.enqueue_query(address, LifxPacket::GetPower, get_power(sequence), LifxPacket::StatePower)).collect();
+
+    let outcomes = outcomes(receivers).await;
+    let report = scheduler.flush().await;
+
+    assert!(outcomes.iter().all(|outcome| matches!(outcome, Outcome::Responded(_))), "{:?}", outcomes);
+    assert!(started.elapsed().as_millis() >= 100, "{:?}", started.elapsed());
+
+    /* Queries don't count towards the commands reported as sent or delayed */
+    assert_eq!(report, SchedulerReport { queried: 3, ..Default::default() });
+}
+
+#[tokio::test(start_paused = true)]
+async fn queries_are_never_coalesced() {
+    let (_device, address) = silent_device().await;
+    let scheduler = Scheduler::new(20.0).unwrap();
+
+    for sequence in 0..2 {
+        drop(scheduler.enqueue(address, LifxPacket::GetPower, get_power(sequence)));
+    }
+
+    let report = scheduler.flush().await;
+
+    assert_eq!(report.coalesced, 0);
+    assert_eq!(report.sent, 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn acknowledged_packets_are_reported() {
+    let (address, _received) = answering_device(LifxPacket::Acknowledgement).await;
+    let scheduler = Scheduler::new(20.0).unwrap();
+
+    let receivers = vec![
+        scheduler.enqueue(address, LifxPacket::SetColor, set_color(0, 0.0)),
+        scheduler.enqueue(address, LifxPacket::GetPower, get_power(1)),
+    ];
+
+    let report = scheduler.flush().await;
+
+    assert_eq!(outcomes(receivers).await, vec![Outcome::Acknowledged; 2]);
+    assert_eq!(report.acknowledged, 2);
+    assert_eq!(report.timed_out, 0);
+}
@@ -0,0 +1,79 @@
+use lifx_cli::{error::LifxError, lan::selector::Selector, types::{Group, LanDevice, Location}};
+
+fn device(id: &str, label: &str, group: &str, location: &str) -> LanDevice {
+    LanDevice {
+        id: id.to_string(),
+        label: label.to_string(),
+        address: None,
+        group: Group { id: format!("{}-group-id", group.to_lowercase()), name: group.to_string() },
+        location: Location { id: format!("{}-location-id", location.to_lowercase()), name: location.to_string() },
+        serial: [0xd0, 0x73, 0xd5, 0, 0, 1],
+    }
+}
+
+fn parse(selector: &str) -> Selector {
+    selector.parse().unwrap_or_else(|error| panic!("'{}' should parse: {}", selector, error))
+}
+
+#[test]
+fn all_matches_every_device() {
+    assert_eq!(parse("all"), Selector::All);
+    assert_eq!(parse(" all "), Selector::All);
+    assert!(parse("all").matches(&device("d073d5000001", "Kitchen", "Downstairs", "Home")));
+}
+
+#[test]
+fn id_matches_ignoring_case() {
+    let kitchen = device("d073d5000001", "Kitchen", "Downstairs", "Home");
+
+    assert_eq!(parse("id:d073d5000001"), Selector::Id(String::from("d073d5000001")));
+    assert!(parse("id:D073D5000001").matches(&kitchen));
+    assert!(!parse("id:d073d5000002").matches(&kitchen));
+}
+
+#[test]
+fn label_matches_ignoring_case() {
+    let kitchen = device("d073d5000001", "Kitchen Lamp", "Downstairs", "Home");
+
+    assert!(parse("label:kitchen lamp").matches(&kitchen));
+    assert!(!parse("label:Kitchen").matches(&kitchen));
+}
+
+#[test]
+fn groups_match_by_name_or_id() {
+    let kitchen = device("d073d5000001", "Kitchen", "Downstairs", "Home");
+
+    assert!(parse("group:downstairs").matches(&kitchen));
+    assert!(parse("group_id:downstairs-group-id").matches(&kitchen));
+    assert!(!parse("group:Upstairs").matches(&kitchen));
+}
+
+#[test]
+fn locations_match_by_name_or_id() {
+    let kitchen = device("d073d5000001", "Kitchen", "Downstairs", "Home");
+
+    assert!(parse("location:HOME").matches(&kitchen));
+    assert!(parse("location_id:home-location-id").matches(&kitchen));
+    assert!(!parse("location:Office").matches(&kitchen));
+}
+
+#[test]
+fn comma_separated_selectors_match_any() {
+    let selector = parse("label:Kitchen, group:Upstairs");
+
+    assert_eq!(selector, Selector::Any(vec![Selector::Label(String::from("Kitchen")), Selector::Group(String::from("Upstairs"))]));
+    assert!(selector.matches(&device("d073d5000001", "Kitchen", "Downstairs", "Home")));
+    assert!(selector.matches(&device("d073d5000002", "Bedroom", "Upstairs", "Home")));
+    assert!(!selector.matches(&device("d073d5000003", "Porch", "Outside", "Home")));
+}
+
+#[test]
+fn invalid_selectors_are_rejected() {
+    for selector in ["", "kitchen", "label:", "scene_id:abc", "id:d073d5000001|0-5", "all,"] {
+        assert!(
+            matches!(selector.parse::<Selector>(), Err(LifxError::Validation(_))),
+            "'{}' should be rejected",
+            selector,
+        );
+    }
+}